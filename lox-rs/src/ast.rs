@@ -1,183 +1,486 @@
-use std::fmt::{Debug, Display};
+use std::{fmt::{Debug, Display}, rc::Rc, cell::RefCell, borrow::Cow};
 
-use crate::{token::Token, error::LoxError};
+use crate::{token::Token, error::LoxError, intern::Symbol, number::Number, primitive::{AsPrimitive, Primitive}, callable::{LoxFunction, LoxNative}, class::{LoxClass, LoxInstance}};
 
-pub trait VisitorExpr<R> {
-    fn visit_assign_expr(&mut self, name: Token, value: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_binary_expr(&mut self, left: Box<Expr>, operator: Token, right: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_call_expr(&self, callee: Box<Expr>, paren: Token, arguments: Vec<Expr>) -> Result<R, LoxError>;
-    fn visit_get_expr(&self, object: Box<Expr>, name: Token) -> Result<R, LoxError>;
-    fn visit_grouping_expr(&mut self, expression: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_literal_expr(&self, value: LoxLiteral) -> Result<R, LoxError>;
-    fn visit_logical_expr(&mut self, left: Box<Expr>, operator: Token, right: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_set_expr(&self, object: Box<Expr>, name: Token, value: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_super_expr(&self, keyword: Token, method: Token) -> Result<R, LoxError>;
-    fn visit_this_expr(&self, keyword: Token) -> Result<R, LoxError>;
-    fn visit_unary_expr(&mut self, operator: Token, right: Box<Expr>) -> Result<R, LoxError>;
-    fn visit_variable_expr(&mut self, name: Token) -> Result<R, LoxError>;
-
-    fn evaluate(&mut self, expr: Expr) -> Result<R, LoxError> {
-        match expr {
-            Expr::Assign { name, value } => self.visit_assign_expr(name, value),
-            Expr::Binary { left, operator, right } => self.visit_binary_expr(left, operator, right),
-            Expr::Call { callee, paren, arguments } => self.visit_call_expr(callee, paren, arguments),
-            Expr::Get { object, name } => self.visit_get_expr(object, name),
-            Expr::Grouping { expression } => self.visit_grouping_expr(expression),
-            Expr::Literal { value } => self.visit_literal_expr(value),
-            Expr::Logical { left, operator, right } => self.visit_logical_expr(left, operator, right),
-            Expr::Set { object, name, value } => self.visit_set_expr(object, name, value),
-            Expr::Super { keyword, method } => self.visit_super_expr(keyword, method),
-            Expr::This { keyword } => self.visit_this_expr(keyword),
-            Expr::Unary { operator, right } => self.visit_unary_expr(operator, right),
-            Expr::Variable { name } => self.visit_variable_expr(name),
+macro_rules! node_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(u32);
+
+        impl $name {
+            #[inline]
+            pub fn index(self) -> usize {
+                self.0 as usize
+            }
+
+            #[inline]
+            pub(crate) fn from_index(index: usize) -> $name {
+                $name(u32::try_from(index).expect("too many AST nodes"))
+            }
         }
+    };
+}
+
+node_id!(
+    /// Index of an expression in its `Ast`.
+    ExprId
+);
+node_id!(
+    /// Index of a statement in its `Ast`.
+    StmtId
+);
+node_id!(
+    /// Index of a token in its `Ast`'s token table.
+    TokenId
+);
+
+/// Where the resolver found a local variable: `depth` scopes out from the
+/// use, at index `slot` in that scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Local {
+    pub depth: usize,
+    pub slot: usize
+}
+
+/// A parsed program. Nodes live in flat arenas and refer to their children
+/// and tokens by index, so a program costs a handful of allocations instead
+/// of one per node and every token is stored exactly once.
+///
+/// Passes that need to remember something per node keep a side table indexed
+/// by the node's id instead of adding fields to `Expr`/`Stmt`. The resolver's
+/// variable slots are kept here because the interpreter needs them for as long
+/// as any closure created from this program is alive.
+#[derive(Debug, Clone, Default)]
+pub struct Ast {
+    tokens: Vec<Token>,
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+    statements: Vec<StmtId>,
+    // Filled in by the resolver: where each variable use finds its binding,
+    // or `None` for a global. Indexed by `ExprId`.
+    locals: Vec<Option<Local>>
+}
+
+impl Ast {
+    #[inline]
+    pub fn new(tokens: Vec<Token>) -> Ast {
+        Ast { tokens, ..Ast::default() }
+    }
+
+    /// The top-level statements, in source order.
+    #[inline]
+    pub fn statements(&self) -> &[StmtId] {
+        &self.statements
+    }
+
+    #[inline]
+    pub fn expr(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+
+    #[inline]
+    pub fn stmt(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id.index()]
+    }
+
+    #[inline]
+    pub fn token(&self, id: TokenId) -> &Token {
+        &self.tokens[id.index()]
+    }
+
+    #[inline]
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    #[inline]
+    pub fn exprs(&self) -> &[Expr] {
+        &self.exprs
+    }
+
+    #[inline]
+    pub fn stmts(&self) -> &[Stmt] {
+        &self.stmts
+    }
+
+    /// The resolved binding of an `Assign`, `Variable`, `This` or `Super`
+    /// expression; `None` for globals and unresolved programs.
+    #[inline]
+    pub fn local(&self, id: ExprId) -> Option<Local> {
+        self.locals.get(id.index()).copied().flatten()
+    }
+
+    #[inline]
+    pub(crate) fn set_locals(&mut self, locals: Vec<Option<Local>>) {
+        self.locals = locals;
+    }
+
+    #[inline]
+    pub(crate) fn expr_mut(&mut self, id: ExprId) -> &mut Expr {
+        &mut self.exprs[id.index()]
+    }
+
+    #[inline]
+    pub(crate) fn stmt_mut(&mut self, id: StmtId) -> &mut Stmt {
+        &mut self.stmts[id.index()]
+    }
+
+    #[inline]
+    pub(crate) fn statements_mut(&mut self) -> &mut Vec<StmtId> {
+        &mut self.statements
+    }
+
+    /// Adds a token the parser made up rather than read, such as the `+`
+    /// joining the parts of an interpolated string.
+    pub(crate) fn push_token(&mut self, token: Token) -> TokenId {
+        self.tokens.push(token);
+        TokenId::from_index(self.tokens.len() - 1)
+    }
+
+    pub(crate) fn push_expr(&mut self, expr: Expr) -> ExprId {
+        self.exprs.push(expr);
+        ExprId::from_index(self.exprs.len() - 1)
+    }
+
+    pub(crate) fn push_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.stmts.push(stmt);
+        StmtId::from_index(self.stmts.len() - 1)
+    }
+
+    pub(crate) fn push_statement(&mut self, stmt: StmtId) {
+        self.statements.push(stmt);
+    }
+}
+
+pub trait VisitorExpr<R> {
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, name: &Token, value: ExprId) -> Result<R, LoxError>;
+    fn visit_binary_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<R, LoxError>;
+    fn visit_call_expr(&mut self, ast: &Ast, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> Result<R, LoxError>;
+    fn visit_get_expr(&mut self, ast: &Ast, object: ExprId, name: &Token) -> Result<R, LoxError>;
+    fn visit_grouping_expr(&mut self, ast: &Ast, expression: ExprId) -> Result<R, LoxError>;
+    fn visit_literal_expr(&mut self, ast: &Ast, value: &LoxLiteral) -> Result<R, LoxError>;
+    fn visit_logical_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<R, LoxError>;
+    fn visit_set_expr(&mut self, ast: &Ast, object: ExprId, name: &Token, value: ExprId) -> Result<R, LoxError>;
+    fn visit_super_expr(&mut self, ast: &Ast, id: ExprId, keyword: &Token, method: &Token) -> Result<R, LoxError>;
+    fn visit_this_expr(&mut self, ast: &Ast, id: ExprId, keyword: &Token) -> Result<R, LoxError>;
+    fn visit_unary_expr(&mut self, ast: &Ast, operator: &Token, right: ExprId) -> Result<R, LoxError>;
+    fn visit_variable_expr(&mut self, ast: &Ast, id: ExprId, name: &Token) -> Result<R, LoxError>;
+
+    /// Visits `expr`. Implementors that need to do something around every
+    /// node can override this and call `walk_expr` themselves.
+    #[inline]
+    fn evaluate(&mut self, ast: &Ast, expr: ExprId) -> Result<R, LoxError> {
+        walk_expr(self, ast, expr)
+    }
+}
+
+/// Calls the `visit_*_expr` method for the kind of `expr`.
+pub fn walk_expr<R, V: VisitorExpr<R> + ?Sized>(visitor: &mut V, ast: &Ast, expr: ExprId) -> Result<R, LoxError> {
+    match ast.expr(expr) {
+        Expr::Assign { name, value } => visitor.visit_assign_expr(ast, expr, ast.token(*name), *value),
+        Expr::Binary { left, operator, right } => visitor.visit_binary_expr(ast, *left, ast.token(*operator), *right),
+        Expr::Call { callee, paren, arguments } => visitor.visit_call_expr(ast, *callee, ast.token(*paren), arguments),
+        Expr::Get { object, name } => visitor.visit_get_expr(ast, *object, ast.token(*name)),
+        Expr::Grouping { expression } => visitor.visit_grouping_expr(ast, *expression),
+        Expr::Literal { value } => visitor.visit_literal_expr(ast, value),
+        Expr::Logical { left, operator, right } => visitor.visit_logical_expr(ast, *left, ast.token(*operator), *right),
+        Expr::Set { object, name, value } => visitor.visit_set_expr(ast, *object, ast.token(*name), *value),
+        Expr::Super { keyword, method } => visitor.visit_super_expr(ast, expr, ast.token(*keyword), ast.token(*method)),
+        Expr::This { keyword } => visitor.visit_this_expr(ast, expr, ast.token(*keyword)),
+        Expr::Unary { operator, right } => visitor.visit_unary_expr(ast, ast.token(*operator), *right),
+        Expr::Variable { name } => visitor.visit_variable_expr(ast, expr, ast.token(*name)),
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     Assign {
-        name: Token,
-        value: Box<Expr>
+        name: TokenId,
+        value: ExprId
     },
     Binary {
-        left: Box<Expr>,
-        operator: Token,
-        right: Box<Expr>
+        left: ExprId,
+        operator: TokenId,
+        right: ExprId
     },
     Call {
-        callee: Box<Expr>,
-        paren: Token,
-        arguments: Vec<Expr>
+        callee: ExprId,
+        paren: TokenId,
+        arguments: Vec<ExprId>
     },
     Get {
-        object: Box<Expr>,
-        name: Token
+        object: ExprId,
+        name: TokenId
     },
     Grouping {
-        expression: Box<Expr>
+        expression: ExprId
     },
     Literal {
         value: LoxLiteral
     },
     Logical {
-        left: Box<Expr>,
-        operator: Token,
-        right: Box<Expr>
+        left: ExprId,
+        operator: TokenId,
+        right: ExprId
     },
     Set {
-        object: Box<Expr>,
-        name: Token,
-        value: Box<Expr>
+        object: ExprId,
+        name: TokenId,
+        value: ExprId
     },
     Super {
-        keyword: Token,
-        method: Token
+        keyword: TokenId,
+        method: TokenId
     },
     This {
-        keyword: Token
+        keyword: TokenId
     },
     Unary {
-        operator: Token,
-        right: Box<Expr>
+        operator: TokenId,
+        right: ExprId
     },
     Variable {
-        name: Token
+        name: TokenId
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum LoxLiteral {
-    String(String),
+    String(Symbol),
+    Int(i64),
     Number(f64),
     Bool(bool),
     Nil
 }
 
+/// The handle of a string value. A NaN-boxed slot can only hold a thin
+/// pointer, so with `nan-boxing` the fat `Rc<str>` gets one more `Rc` around
+/// it, once when the string is made rather than on every store. Build one
+/// with `LoxObject::from` to work with either.
+#[cfg(not(feature = "nan-boxing"))]
+pub type LoxStr = Rc<str>;
+
+#[cfg(feature = "nan-boxing")]
+pub type LoxStr = Rc<Rc<str>>;
+
 #[derive(Clone)]
 pub enum LoxObject {
-    String(String),
+    String(LoxStr),
+    Int(i64),
     Number(f64),
     Bool(bool),
+    Function(Rc<LoxFunction>),
+    Native(Rc<LoxNative>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
     Nil
 }
 
+impl From<&str> for LoxObject {
+    #[inline]
+    fn from(str: &str) -> Self {
+        LoxObject::String(LoxStr::from(Rc::<str>::from(str)))
+    }
+}
+
+impl From<String> for LoxObject {
+    #[inline]
+    fn from(str: String) -> Self {
+        LoxObject::String(LoxStr::from(Rc::<str>::from(str)))
+    }
+}
+
 impl Display for LoxObject {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let r = match self {
             LoxObject::String(_) => "string",
+            LoxObject::Int(_) => "int",
             LoxObject::Number(_) => "number",
             LoxObject::Bool(_) => "bool",
+            LoxObject::Function(_) => "function",
+            LoxObject::Native(_) => "native function",
+            LoxObject::Class(_) => "class",
+            LoxObject::Instance(_) => "instance",
             LoxObject::Nil => "nil",
         };
         write!(f, "{}", r)
     }
 }
 
-pub trait VisitorStmt<R> {
-    fn visit_block_stmt(&mut self, statements: Vec<Stmt>) -> Result<R, LoxError>;
-    fn visit_class_stmt(&self, name: Token, superclass: Option<Expr>, methods: Vec<Stmt>) -> Result<R, LoxError>;
-    fn visit_expression_stmt(&mut self, expression: Expr) -> Result<R, LoxError>;
-    fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Stmt>) -> Result<R, LoxError>;
-    fn visit_if_stmt(&mut self, condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>) -> Result<R, LoxError>;
-    fn visit_print_stmt(&mut self, expression: Expr) -> Result<R, LoxError>;
-    fn visit_return_stmt(&self, keyword: Token, value: Option<Expr>) -> Result<R, LoxError>;
-    fn visit_var_stmt(&mut self, name: Token, initializer: Option<Expr>) -> Result<R, LoxError>;
-    fn visit_while_stmt(&mut self, condition: Expr, body: Box<Stmt>) -> Result<R, LoxError>;
-
-    fn execute(&mut self, stmt: Stmt) -> Result<R, LoxError> {
-        match stmt {
-            Stmt::Block { statements } => self.visit_block_stmt(statements),
-            Stmt::Class { name, superclass, methods } => self.visit_class_stmt(name, superclass, methods),
-            Stmt::Expression { expression } => self.visit_expression_stmt(expression),
-            Stmt::Function { name, params, body } => self.visit_function_stmt(name, params, body),
-            Stmt::If { condition, then_branch, else_branch } => self.visit_if_stmt(condition, then_branch, else_branch),
-            Stmt::Print { expression } => self.visit_print_stmt(expression),
-            Stmt::Return { keyword, value } => self.visit_return_stmt(keyword, value),
-            Stmt::Var { name, initializer } => self.visit_var_stmt(name, initializer),
-            Stmt::While { condition, body } => self.visit_while_stmt(condition, body),
+impl LoxLiteral {
+    /// The literal as an operand of arithmetic, if it is a number.
+    #[inline]
+    pub(crate) fn as_number(&self) -> Option<Number> {
+        match self {
+            LoxLiteral::Int(int) => Some(Number::Int(*int)),
+            LoxLiteral::Number(num) => Some(Number::Float(*num)),
+            _ => None
         }
     }
 }
 
+impl AsPrimitive for LoxLiteral {
+    fn as_primitive(&self) -> Primitive<'_> {
+        match self {
+            LoxLiteral::String(str) => Primitive::String(Cow::Owned(str.as_rc().to_string())),
+            LoxLiteral::Int(int) => Primitive::Number(Number::Int(*int)),
+            LoxLiteral::Number(num) => Primitive::Number(Number::Float(*num)),
+            LoxLiteral::Bool(bool) => Primitive::Bool(*bool),
+            LoxLiteral::Nil => Primitive::Nil
+        }
+    }
+
+    // A literal is never an object.
+    #[inline]
+    fn is_same_object(&self, _other: &Self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn object_name(&self) -> String {
+        unreachable!("a literal is never an object")
+    }
+}
+
+impl From<Number> for LoxLiteral {
+    #[inline]
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(int) => LoxLiteral::Int(int),
+            Number::Float(num) => LoxLiteral::Number(num)
+        }
+    }
+}
+
+impl LoxObject {
+    /// The value as an operand of arithmetic, if it is a number.
+    #[inline]
+    pub(crate) fn as_number(&self) -> Option<Number> {
+        match self {
+            LoxObject::Int(int) => Some(Number::Int(*int)),
+            LoxObject::Number(num) => Some(Number::Float(*num)),
+            _ => None
+        }
+    }
+}
+
+impl AsPrimitive for LoxObject {
+    #[inline]
+    fn as_primitive(&self) -> Primitive<'_> {
+        match self {
+            LoxObject::String(str) => Primitive::String(Cow::Borrowed(str)),
+            LoxObject::Int(int) => Primitive::Number(Number::Int(*int)),
+            LoxObject::Number(num) => Primitive::Number(Number::Float(*num)),
+            LoxObject::Bool(bool) => Primitive::Bool(*bool),
+            LoxObject::Nil => Primitive::Nil,
+            LoxObject::Function(_) | LoxObject::Native(_) | LoxObject::Class(_) | LoxObject::Instance(_) => Primitive::Object
+        }
+    }
+
+    fn is_same_object(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LoxObject::Function(l), LoxObject::Function(r)) => Rc::ptr_eq(l, r),
+            (LoxObject::Native(l), LoxObject::Native(r)) => Rc::ptr_eq(l, r),
+            (LoxObject::Class(l), LoxObject::Class(r)) => Rc::ptr_eq(l, r),
+            (LoxObject::Instance(l), LoxObject::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false
+        }
+    }
+
+    fn object_name(&self) -> String {
+        match self {
+            LoxObject::Function(function) => format!("<fn {}>", function.name()),
+            LoxObject::Native(native) => format!("<native fn {}>", native.name()),
+            LoxObject::Class(class) => class.name().to_owned(),
+            LoxObject::Instance(instance) => format!("{} instance", instance.borrow().class().name()),
+            _ => unreachable!("{} is not an object", self)
+        }
+    }
+}
+
+impl From<Number> for LoxObject {
+    #[inline]
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(int) => LoxObject::Int(int),
+            Number::Float(num) => LoxObject::Number(num)
+        }
+    }
+}
+
+pub trait VisitorStmt<R> {
+    fn visit_block_stmt(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<R, LoxError>;
+    fn visit_class_stmt(&mut self, ast: &Ast, name: &Token, superclass: Option<ExprId>, methods: &[StmtId]) -> Result<R, LoxError>;
+    fn visit_expression_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<R, LoxError>;
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, name: &Token, params: &[TokenId], body: &[StmtId]) -> Result<R, LoxError>;
+    fn visit_if_stmt(&mut self, ast: &Ast, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Result<R, LoxError>;
+    fn visit_print_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<R, LoxError>;
+    fn visit_return_stmt(&mut self, ast: &Ast, keyword: &Token, value: Option<ExprId>) -> Result<R, LoxError>;
+    fn visit_var_stmt(&mut self, ast: &Ast, name: &Token, initializer: Option<ExprId>) -> Result<R, LoxError>;
+    fn visit_while_stmt(&mut self, ast: &Ast, condition: ExprId, body: StmtId) -> Result<R, LoxError>;
+
+    /// Visits `stmt`. Implementors that need to do something around every
+    /// node can override this and call `walk_stmt` themselves.
+    #[inline]
+    fn execute(&mut self, ast: &Ast, stmt: StmtId) -> Result<R, LoxError> {
+        walk_stmt(self, ast, stmt)
+    }
+}
+
+/// Calls the `visit_*_stmt` method for the kind of `stmt`.
+pub fn walk_stmt<R, V: VisitorStmt<R> + ?Sized>(visitor: &mut V, ast: &Ast, stmt: StmtId) -> Result<R, LoxError> {
+    match ast.stmt(stmt) {
+        Stmt::Block { statements } => visitor.visit_block_stmt(ast, statements),
+        Stmt::Class { name, superclass, methods } => visitor.visit_class_stmt(ast, ast.token(*name), *superclass, methods),
+        Stmt::Expression { expression } => visitor.visit_expression_stmt(ast, *expression),
+        Stmt::Function { name, params, body } => visitor.visit_function_stmt(ast, stmt, ast.token(*name), params, body),
+        Stmt::If { condition, then_branch, else_branch } => visitor.visit_if_stmt(ast, *condition, *then_branch, *else_branch),
+        Stmt::Print { expression } => visitor.visit_print_stmt(ast, *expression),
+        Stmt::Return { keyword, value } => visitor.visit_return_stmt(ast, ast.token(*keyword), *value),
+        Stmt::Var { name, initializer } => visitor.visit_var_stmt(ast, ast.token(*name), *initializer),
+        Stmt::While { condition, body } => visitor.visit_while_stmt(ast, *condition, *body),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Block {
-        statements: Vec<Stmt>
+        statements: Vec<StmtId>
     },
     Class {
-        name: Token,
+        name: TokenId,
         // Expr::Variable
-        superclass: Option<Expr>,
+        superclass: Option<ExprId>,
         // Stmt::Function
-        methods: Vec<Stmt>
+        methods: Vec<StmtId>
     },
     Expression {
-        expression: Expr
+        expression: ExprId
     },
     Function {
-        name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>
+        name: TokenId,
+        params: Vec<TokenId>,
+        body: Vec<StmtId>
     },
     If {
-        condition: Expr,
-        then_branch: Box<Stmt>,
-        else_branch: Option<Box<Stmt>>
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>
     },
     Print {
-        expression: Expr
+        expression: ExprId
     },
     Return {
-        keyword: Token,
-        value: Option<Expr>
+        keyword: TokenId,
+        value: Option<ExprId>
     },
     Var {
-        name: Token,
-        initializer: Option<Expr>
+        name: TokenId,
+        initializer: Option<ExprId>
     },
     While {
-        condition: Expr,
-        body: Box<Stmt>
+        condition: ExprId,
+        body: StmtId
     }
-}
\ No newline at end of file
+}
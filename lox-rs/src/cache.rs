@@ -0,0 +1,630 @@
+use std::fmt::Display;
+
+use crate::{
+    ast::{Ast, Expr, ExprId, LoxLiteral, Stmt, StmtId, TokenId},
+    intern::Symbol,
+    token::{LoxType, Span, Token, TokenType},
+};
+
+// File layout, all integers little-endian:
+//
+//   magic     4 bytes  b"\x7fLOX"
+//   version   u16      FORMAT_VERSION
+//   checksum  u64      FNV-1a of the payload
+//   length    u64      payload size in bytes
+//   payload            the encoded `Ast`: its token table, expression and
+//                      statement arenas, then the top-level statement ids
+//
+// Bump FORMAT_VERSION whenever the encoding of a node or token changes.
+//
+// The magic starts with a byte the scanner rejects, so no valid Lox program
+// can be mistaken for a compiled one.
+pub const MAGIC: &[u8; 4] = b"\x7fLOX";
+pub const FORMAT_VERSION: u16 = 5;
+
+const HEADER_LEN: usize = 4 + 2 + 8 + 8;
+
+// Indices into this table are the on-disk encoding of `TokenType`.
+const TOKEN_TYPES: [TokenType; 42] = [
+    TokenType::LeftParen, TokenType::RightParen, TokenType::LeftBrace, TokenType::RightBrace,
+    TokenType::Comma, TokenType::Dot, TokenType::Minus, TokenType::Percent, TokenType::Plus,
+    TokenType::Semicolon, TokenType::Slash, TokenType::Star,
+    TokenType::Bang, TokenType::BangEqual,
+    TokenType::Equal, TokenType::EqualEqual,
+    TokenType::Greater, TokenType::GreaterEqual,
+    TokenType::Less, TokenType::LessEqual,
+    TokenType::SlashSlash,
+    TokenType::Identifier, TokenType::String, TokenType::Interpolation, TokenType::Number,
+    TokenType::And, TokenType::Class, TokenType::Else, TokenType::False, TokenType::Fun,
+    TokenType::For, TokenType::If, TokenType::Nil, TokenType::Or,
+    TokenType::Print, TokenType::Return, TokenType::Super, TokenType::This, TokenType::True,
+    TokenType::Var, TokenType::While,
+    TokenType::Eof,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CacheError {
+    BadMagic,
+    UnsupportedVersion { found: u16 },
+    ChecksumMismatch,
+    Truncated,
+    Malformed(Box<str>),
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::BadMagic => write!(f, "CacheError not a compiled lox program."),
+            CacheError::UnsupportedVersion { found } => {
+                write!(f, "CacheError compiled with format version {}, but this lox reads version {}. Recompile it.", found, FORMAT_VERSION)
+            }
+            CacheError::ChecksumMismatch => write!(f, "CacheError checksum mismatch, the file is corrupt."),
+            CacheError::Truncated => write!(f, "CacheError unexpected end of file, the file is corrupt."),
+            CacheError::Malformed(msg) => write!(f, "CacheError {}", msg)
+        }
+    }
+}
+
+#[inline]
+pub fn is_cache(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Serializes a parsed program, keeping every token and its span.
+pub fn save(ast: &Ast) -> Vec<u8> {
+    let mut payload = Writer { bytes: Vec::new() };
+    payload.ast(ast);
+    let payload = payload.bytes;
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Reads back a program written by `save`, checking the header and checksum
+/// before decoding anything.
+pub fn load(bytes: &[u8]) -> Result<Ast, CacheError> {
+    if !is_cache(bytes) {
+        return Err(CacheError::BadMagic);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(CacheError::Truncated);
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != FORMAT_VERSION {
+        return Err(CacheError::UnsupportedVersion { found: version });
+    }
+
+    let expected = u64::from_le_bytes(bytes[6..14].try_into().unwrap());
+    let length = u64::from_le_bytes(bytes[14..22].try_into().unwrap());
+
+    let payload = &bytes[HEADER_LEN..];
+    if (payload.len() as u64) < length {
+        return Err(CacheError::Truncated);
+    }
+    if payload.len() as u64 > length {
+        return Err(CacheError::Malformed("trailing bytes after the program.".into()));
+    }
+    if checksum(payload) != expected {
+        return Err(CacheError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader { bytes: payload, current: 0, token_count: 0, expr_count: 0 };
+    let ast = reader.ast()?;
+    if reader.current != payload.len() {
+        return Err(CacheError::Malformed("trailing bytes after the program.".into()));
+    }
+    Ok(ast)
+}
+
+/// 64-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+struct Writer {
+    bytes: Vec<u8>
+}
+
+impl Writer {
+    #[inline]
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    #[inline]
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    #[inline]
+    fn len(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    #[inline]
+    fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    #[inline]
+    fn i64(&mut self, value: i64) {
+        self.u64(value as u64);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    #[inline]
+    fn symbol(&mut self, value: Symbol) {
+        self.str(&value.as_rc());
+    }
+
+    fn token(&mut self, token: &Token) {
+        let index = TOKEN_TYPES.iter().position(|&t| t == token.r#type).unwrap();
+        self.u8(index as u8);
+
+        match &token.lexeme {
+            Some(lexeme) => {
+                self.u8(1);
+                self.symbol(*lexeme);
+            }
+            None => self.u8(0)
+        }
+
+        match &token.literal {
+            LoxType::Id(id) => {
+                self.u8(0);
+                self.symbol(*id);
+            }
+            LoxType::String(str) => {
+                self.u8(1);
+                self.symbol(*str);
+            }
+            LoxType::Number(num) => {
+                self.u8(2);
+                self.f64(*num);
+            }
+            LoxType::Nil => self.u8(3),
+            LoxType::Int(int) => {
+                self.u8(4);
+                self.i64(*int);
+            }
+        }
+
+        self.len(token.span.start);
+        self.len(token.span.end);
+        self.u32(token.span.line);
+        self.u32(token.span.column);
+    }
+
+    fn tokens(&mut self, tokens: &[Token]) {
+        self.len(tokens.len());
+        tokens.iter().for_each(|token| self.token(token));
+    }
+
+    #[inline]
+    fn id(&mut self, index: usize) {
+        self.u32(index as u32);
+    }
+
+    #[inline]
+    fn expr_id(&mut self, id: &ExprId) {
+        self.id(id.index());
+    }
+
+    #[inline]
+    fn stmt_id(&mut self, id: &StmtId) {
+        self.id(id.index());
+    }
+
+    #[inline]
+    fn token_id(&mut self, id: &TokenId) {
+        self.id(id.index());
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value } => {
+                self.u8(0);
+                self.token_id(name);
+                self.expr_id(value);
+            }
+            Expr::Binary { left, operator, right } => {
+                self.u8(1);
+                self.expr_id(left);
+                self.token_id(operator);
+                self.expr_id(right);
+            }
+            Expr::Call { callee, paren, arguments } => {
+                self.u8(2);
+                self.expr_id(callee);
+                self.token_id(paren);
+                self.len(arguments.len());
+                arguments.iter().for_each(|argument| self.expr_id(argument));
+            }
+            Expr::Get { object, name } => {
+                self.u8(3);
+                self.expr_id(object);
+                self.token_id(name);
+            }
+            Expr::Grouping { expression } => {
+                self.u8(4);
+                self.expr_id(expression);
+            }
+            Expr::Literal { value } => {
+                self.u8(5);
+                match value {
+                    LoxLiteral::String(str) => {
+                        self.u8(0);
+                        self.symbol(*str);
+                    }
+                    LoxLiteral::Number(num) => {
+                        self.u8(1);
+                        self.f64(*num);
+                    }
+                    LoxLiteral::Bool(bool) => {
+                        self.u8(2);
+                        self.u8(*bool as u8);
+                    }
+                    LoxLiteral::Nil => self.u8(3),
+                    LoxLiteral::Int(int) => {
+                        self.u8(4);
+                        self.i64(*int);
+                    }
+                }
+            }
+            Expr::Logical { left, operator, right } => {
+                self.u8(6);
+                self.expr_id(left);
+                self.token_id(operator);
+                self.expr_id(right);
+            }
+            Expr::Set { object, name, value } => {
+                self.u8(7);
+                self.expr_id(object);
+                self.token_id(name);
+                self.expr_id(value);
+            }
+            Expr::Super { keyword, method } => {
+                self.u8(8);
+                self.token_id(keyword);
+                self.token_id(method);
+            }
+            Expr::This { keyword } => {
+                self.u8(9);
+                self.token_id(keyword);
+            }
+            Expr::Unary { operator, right } => {
+                self.u8(10);
+                self.token_id(operator);
+                self.expr_id(right);
+            }
+            Expr::Variable { name } => {
+                self.u8(11);
+                self.token_id(name);
+            }
+        }
+    }
+
+    fn opt_expr(&mut self, expr: &Option<ExprId>) {
+        match expr {
+            Some(expr) => {
+                self.u8(1);
+                self.expr_id(expr);
+            }
+            None => self.u8(0)
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => {
+                self.u8(0);
+                self.stmt_ids(statements);
+            }
+            Stmt::Class { name, superclass, methods } => {
+                self.u8(1);
+                self.token_id(name);
+                self.opt_expr(superclass);
+                self.stmt_ids(methods);
+            }
+            Stmt::Expression { expression } => {
+                self.u8(2);
+                self.expr_id(expression);
+            }
+            Stmt::Function { name, params, body } => {
+                self.u8(3);
+                self.token_id(name);
+                self.len(params.len());
+                params.iter().for_each(|param| self.token_id(param));
+                self.stmt_ids(body);
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                self.u8(4);
+                self.expr_id(condition);
+                self.stmt_id(then_branch);
+                match else_branch {
+                    Some(stmt) => {
+                        self.u8(1);
+                        self.stmt_id(stmt);
+                    }
+                    None => self.u8(0)
+                }
+            }
+            Stmt::Print { expression } => {
+                self.u8(5);
+                self.expr_id(expression);
+            }
+            Stmt::Return { keyword, value } => {
+                self.u8(6);
+                self.token_id(keyword);
+                self.opt_expr(value);
+            }
+            Stmt::Var { name, initializer } => {
+                self.u8(7);
+                self.token_id(name);
+                self.opt_expr(initializer);
+            }
+            Stmt::While { condition, body } => {
+                self.u8(8);
+                self.expr_id(condition);
+                self.stmt_id(body);
+            }
+        }
+    }
+
+    fn stmt_ids(&mut self, statements: &[StmtId]) {
+        self.len(statements.len());
+        statements.iter().for_each(|stmt| self.stmt_id(stmt));
+    }
+
+    fn ast(&mut self, ast: &Ast) {
+        self.tokens(ast.tokens());
+        self.len(ast.exprs().len());
+        ast.exprs().iter().for_each(|expr| self.expr(expr));
+        self.len(ast.stmts().len());
+        ast.stmts().iter().for_each(|stmt| self.stmt(stmt));
+        self.stmt_ids(ast.statements());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    current: usize,
+    // Sizes of the arenas decoded so far, used to bounds-check references.
+    token_count: usize,
+    expr_count: usize
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], CacheError> {
+        if self.bytes.len() - self.current < n {
+            return Err(CacheError::Truncated);
+        }
+        self.current += n;
+        Ok(&self.bytes[self.current - n..self.current])
+    }
+
+    #[inline]
+    fn u8(&mut self) -> Result<u8, CacheError> {
+        Ok(self.take(1)?[0])
+    }
+
+    #[inline]
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    #[inline]
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a length prefix, rejecting any that could not fit in the rest of
+    /// the payload so corrupt input never triggers a huge allocation.
+    fn len(&mut self) -> Result<usize, CacheError> {
+        let len = self.u64()?;
+        if len > (self.bytes.len() - self.current) as u64 {
+            return Err(CacheError::Truncated);
+        }
+        Ok(len as usize)
+    }
+
+    #[inline]
+    fn f64(&mut self) -> Result<f64, CacheError> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    #[inline]
+    fn i64(&mut self) -> Result<i64, CacheError> {
+        Ok(self.u64()? as i64)
+    }
+
+    fn bool(&mut self) -> Result<bool, CacheError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(malformed("flag", tag))
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CacheError> {
+        let len = self.len()?;
+        let bytes = self.take(len)?.to_vec();
+        String::from_utf8(bytes).map_err(|_| CacheError::Malformed("string is not valid UTF-8.".into()))
+    }
+
+    #[inline]
+    fn symbol(&mut self) -> Result<Symbol, CacheError> {
+        Ok(Symbol::intern(&self.string()?))
+    }
+
+    fn token(&mut self) -> Result<Token, CacheError> {
+        let tag = self.u8()?;
+        let r#type = *TOKEN_TYPES.get(tag as usize).ok_or_else(|| malformed("token type", tag))?;
+
+        let lexeme = if self.bool()? { Some(self.symbol()?) } else { None };
+
+        let literal = match self.u8()? {
+            0 => LoxType::Id(self.symbol()?),
+            1 => LoxType::String(self.symbol()?),
+            2 => LoxType::Number(self.f64()?),
+            3 => LoxType::Nil,
+            4 => LoxType::Int(self.i64()?),
+            tag => return Err(malformed("literal", tag))
+        };
+
+        let start = self.u64()? as usize;
+        let end = self.u64()? as usize;
+        let line = self.u32()?;
+        let column = self.u32()?;
+
+        // Doc comments are only of use to tools reading the source, so they
+        // aren't stored.
+        Ok(Token { r#type, lexeme, literal, span: Span { start, end, line, column }, doc: None })
+    }
+
+    fn tokens(&mut self) -> Result<Vec<Token>, CacheError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.token()).collect()
+    }
+
+    /// Reads a node index and checks it is below `limit`. Children are always
+    /// allocated before their parents, so requiring every reference to point
+    /// backwards also rules out cycles in a corrupt file.
+    fn id(&mut self, limit: usize) -> Result<usize, CacheError> {
+        let index = self.u32()? as usize;
+        if index >= limit {
+            return Err(CacheError::Malformed(format!("node index {} is out of range.", index).into()));
+        }
+        Ok(index)
+    }
+
+    #[inline]
+    fn expr_id(&mut self, limit: usize) -> Result<ExprId, CacheError> {
+        Ok(ExprId::from_index(self.id(limit)?))
+    }
+
+    #[inline]
+    fn stmt_id(&mut self, limit: usize) -> Result<StmtId, CacheError> {
+        Ok(StmtId::from_index(self.id(limit)?))
+    }
+
+    #[inline]
+    fn token_id(&mut self) -> Result<TokenId, CacheError> {
+        Ok(TokenId::from_index(self.id(self.token_count)?))
+    }
+
+    // `index` is the id the decoded expression will get.
+    fn expr(&mut self, index: usize) -> Result<Expr, CacheError> {
+        Ok(match self.u8()? {
+            0 => Expr::Assign { name: self.token_id()?, value: self.expr_id(index)? },
+            1 => Expr::Binary { left: self.expr_id(index)?, operator: self.token_id()?, right: self.expr_id(index)? },
+            2 => {
+                let callee = self.expr_id(index)?;
+                let paren = self.token_id()?;
+                let len = self.len()?;
+                let arguments = (0..len).map(|_| self.expr_id(index)).collect::<Result<_, _>>()?;
+                Expr::Call { callee, paren, arguments }
+            }
+            3 => Expr::Get { object: self.expr_id(index)?, name: self.token_id()? },
+            4 => Expr::Grouping { expression: self.expr_id(index)? },
+            5 => {
+                let value = match self.u8()? {
+                    0 => LoxLiteral::String(self.symbol()?),
+                    1 => LoxLiteral::Number(self.f64()?),
+                    2 => LoxLiteral::Bool(self.bool()?),
+                    3 => LoxLiteral::Nil,
+                    4 => LoxLiteral::Int(self.i64()?),
+                    tag => return Err(malformed("literal", tag))
+                };
+                Expr::Literal { value }
+            }
+            6 => Expr::Logical { left: self.expr_id(index)?, operator: self.token_id()?, right: self.expr_id(index)? },
+            7 => Expr::Set { object: self.expr_id(index)?, name: self.token_id()?, value: self.expr_id(index)? },
+            8 => Expr::Super { keyword: self.token_id()?, method: self.token_id()? },
+            9 => Expr::This { keyword: self.token_id()? },
+            10 => Expr::Unary { operator: self.token_id()?, right: self.expr_id(index)? },
+            11 => Expr::Variable { name: self.token_id()? },
+            tag => return Err(malformed("expression", tag))
+        })
+    }
+
+    fn opt_expr(&mut self) -> Result<Option<ExprId>, CacheError> {
+        Ok(if self.bool()? { Some(self.expr_id(self.expr_count)?) } else { None })
+    }
+
+    // `index` is the id the decoded statement will get.
+    fn stmt(&mut self, index: usize) -> Result<Stmt, CacheError> {
+        let exprs = self.expr_count;
+        Ok(match self.u8()? {
+            0 => Stmt::Block { statements: self.stmt_ids(index)? },
+            1 => Stmt::Class { name: self.token_id()?, superclass: self.opt_expr()?, methods: self.stmt_ids(index)? },
+            2 => Stmt::Expression { expression: self.expr_id(exprs)? },
+            3 => {
+                let name = self.token_id()?;
+                let len = self.len()?;
+                let params = (0..len).map(|_| self.token_id()).collect::<Result<_, _>>()?;
+                Stmt::Function { name, params, body: self.stmt_ids(index)? }
+            }
+            4 => {
+                let condition = self.expr_id(exprs)?;
+                let then_branch = self.stmt_id(index)?;
+                let else_branch = if self.bool()? { Some(self.stmt_id(index)?) } else { None };
+                Stmt::If { condition, then_branch, else_branch }
+            }
+            5 => Stmt::Print { expression: self.expr_id(exprs)? },
+            6 => Stmt::Return { keyword: self.token_id()?, value: self.opt_expr()? },
+            7 => Stmt::Var { name: self.token_id()?, initializer: self.opt_expr()? },
+            8 => Stmt::While { condition: self.expr_id(exprs)?, body: self.stmt_id(index)? },
+            tag => return Err(malformed("statement", tag))
+        })
+    }
+
+    fn stmt_ids(&mut self, limit: usize) -> Result<Vec<StmtId>, CacheError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.stmt_id(limit)).collect()
+    }
+
+    fn ast(&mut self) -> Result<Ast, CacheError> {
+        let tokens = self.tokens()?;
+        self.token_count = tokens.len();
+        let mut ast = Ast::new(tokens);
+
+        let len = self.len()?;
+        for index in 0..len {
+            let expr = self.expr(index)?;
+            ast.push_expr(expr);
+        }
+        self.expr_count = len;
+
+        let len = self.len()?;
+        for index in 0..len {
+            let stmt = self.stmt(index)?;
+            ast.push_stmt(stmt);
+        }
+
+        for stmt in self.stmt_ids(len)? {
+            ast.push_statement(stmt);
+        }
+        Ok(ast)
+    }
+}
+
+#[inline]
+fn malformed(what: &str, tag: u8) -> CacheError {
+    CacheError::Malformed(format!("unknown {} tag {}.", what, tag).into())
+}
@@ -0,0 +1,136 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::{
+    ast::{Ast, LoxObject, Stmt, StmtId, TokenId},
+    environment::Environment,
+    error::LoxError,
+    gc::{self, Node, Trace},
+    intern::Symbol,
+    interpreter::{Interpreter, Completion},
+    token::Token,
+    class::LoxInstance,
+};
+
+pub trait LoxCallable {
+    fn arity(&self) -> usize;
+    // `paren` is the call site's closing parenthesis, used to locate errors.
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<LoxObject>, paren: &Token) -> Result<LoxObject, LoxError>;
+}
+
+pub struct LoxFunction {
+    // The program the function was parsed from, and its `Stmt::Function`.
+    ast: Rc<Ast>,
+    declaration: StmtId,
+    closure: Option<Rc<RefCell<Environment>>>,
+    is_initializer: bool
+}
+
+impl LoxFunction {
+    #[inline]
+    pub fn new(ast: Rc<Ast>, declaration: StmtId, closure: Option<Rc<RefCell<Environment>>>, is_initializer: bool) -> LoxFunction {
+        if let Some(closure) = &closure {
+            gc::track_environment(closure);
+        }
+        LoxFunction { ast, declaration, closure, is_initializer }
+    }
+
+    #[inline]
+    pub fn name(&self) -> Symbol {
+        let (name, _, _) = self.declaration();
+        name.name()
+    }
+
+    fn declaration(&self) -> (&Token, &[TokenId], &[StmtId]) {
+        match self.ast.stmt(self.declaration) {
+            Stmt::Function { name, params, body } => (self.ast.token(*name), params, body),
+            _ => unreachable!("a function is always declared by Stmt::Function")
+        }
+    }
+
+    /// Returns a copy of this method whose closure binds `this` to `instance`.
+    pub fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        let mut environment = Environment::from(self.closure.clone());
+        environment.define(LoxObject::Instance(instance));
+
+        LoxFunction {
+            ast: self.ast.clone(),
+            declaration: self.declaration,
+            closure: Some(Rc::new(RefCell::new(environment))),
+            is_initializer: self.is_initializer
+        }
+    }
+}
+
+impl LoxCallable for LoxFunction {
+    #[inline]
+    fn arity(&self) -> usize {
+        let (_, params, _) = self.declaration();
+        params.len()
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<LoxObject>, _paren: &Token) -> Result<LoxObject, LoxError> {
+        let mut environment = Environment::from(self.closure.clone());
+        let (_, _, body) = self.declaration();
+
+        // Parameters take the first slots, in order; the caller has checked
+        // the arity.
+        for argument in arguments {
+            environment.define(argument);
+        }
+
+        let completion = interpreter.execute_body(&self.ast, body, Rc::new(RefCell::new(environment)))?;
+
+        if self.is_initializer {
+            // A bound method's closure holds nothing but `this`.
+            let this = self.closure.as_ref().and_then(|closure| closure.borrow().get_here(0));
+            return Ok(this.unwrap_or(LoxObject::Nil));
+        }
+
+        match completion {
+            Completion::Return(value) => Ok(value),
+            Completion::Normal => Ok(LoxObject::Nil)
+        }
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, children: &mut Vec<Node>) {
+        children.extend(self.closure.clone().map(Node::Environment));
+    }
+}
+
+pub type NativeFn = dyn Fn(&[LoxObject]) -> Result<LoxObject, String>;
+
+/// A function implemented in Rust by the host and exposed to scripts through
+/// `Interpreter::define_native`.
+pub struct LoxNative {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>
+}
+
+impl LoxNative {
+    #[inline]
+    pub fn new<F>(name: String, arity: usize, function: F) -> LoxNative
+    where
+        F: Fn(&[LoxObject]) -> Result<LoxObject, String> + 'static
+    {
+        LoxNative { name, arity, function: Box::new(function) }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl LoxCallable for LoxNative {
+    #[inline]
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(self: Rc<Self>, _interpreter: &mut Interpreter, arguments: Vec<LoxObject>, paren: &Token) -> Result<LoxObject, LoxError> {
+        (self.function)(&arguments).map_err(|msg| LoxError::RuntimeError { msg: msg.into(), span: paren.span })
+    }
+}
@@ -0,0 +1,115 @@
+use std::{rc::Rc, cell::RefCell, collections::HashMap};
+
+use crate::{
+    ast::LoxObject,
+    callable::{LoxCallable, LoxFunction},
+    error::LoxError,
+    gc::{self, Node, Trace},
+    intern::Symbol,
+    value::{Slot, Value},
+    interpreter::Interpreter,
+    token::Token,
+};
+
+pub struct LoxClass {
+    name: Rc<str>,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, Rc<LoxFunction>>
+}
+
+impl LoxClass {
+    #[inline]
+    pub fn new(name: Rc<str>, superclass: Option<Rc<LoxClass>>, methods: HashMap<Symbol, Rc<LoxFunction>>) -> LoxClass {
+        LoxClass { name, superclass, methods }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Looks up a method on this class, then walks up the superclass chain.
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref().and_then(|superclass| superclass.find_method(name))
+        }
+    }
+}
+
+impl LoxCallable for LoxClass {
+    fn arity(&self) -> usize {
+        match self.find_method(Symbol::INIT) {
+            Some(initializer) => initializer.arity(),
+            None => 0
+        }
+    }
+
+    fn call(self: Rc<Self>, interpreter: &mut Interpreter, arguments: Vec<LoxObject>, paren: &Token) -> Result<LoxObject, LoxError> {
+        let instance = Rc::new(RefCell::new(LoxInstance::new(self.clone())));
+        gc::track_instance(&instance);
+
+        if let Some(initializer) = self.find_method(Symbol::INIT) {
+            Rc::new(initializer.bind(instance.clone())).call(interpreter, arguments, paren)?;
+        }
+
+        Ok(LoxObject::Instance(instance))
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<Symbol, Value>
+}
+
+impl LoxInstance {
+    #[inline]
+    pub fn new(class: Rc<LoxClass>) -> LoxInstance {
+        LoxInstance { class, fields: HashMap::new() }
+    }
+
+    #[inline]
+    pub fn class(&self) -> &Rc<LoxClass> {
+        &self.class
+    }
+
+    /// Looks up a field first and falls back to a method bound to `instance`.
+    pub fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Token) -> Result<LoxObject, LoxError> {
+        let lexeme = name.name();
+
+        if let Some(value) = instance.borrow().fields.get(&lexeme) {
+            return Ok(value.load());
+        }
+
+        let method = instance.borrow().class.find_method(lexeme);
+        if let Some(method) = method {
+            return Ok(LoxObject::Function(Rc::new(method.bind(instance.clone()))));
+        }
+
+        Err(LoxError::RuntimeError { msg: format!("Undefined property '{}'.", lexeme).into(), span: name.span })
+    }
+
+    #[inline]
+    pub fn set(&mut self, name: &Token, value: LoxObject) {
+        self.fields.insert(name.name(), Value::store(value));
+    }
+
+    /// Empties an instance the cycle collector found unreachable.
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, children: &mut Vec<Node>) {
+        children.extend(self.superclass.clone().map(Node::Class));
+        children.extend(self.methods.values().cloned().map(Node::Function));
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, children: &mut Vec<Node>) {
+        children.push(Node::Class(self.class.clone()));
+        children.extend(self.fields.values().filter_map(|value| Node::from_object(value.load())));
+    }
+}
@@ -0,0 +1,613 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, Expr, ExprId, LoxLiteral, Stmt, StmtId, TokenId, VisitorExpr, VisitorStmt},
+    error::LoxError,
+    intern::Symbol,
+    token::{Span, Token, TokenType},
+};
+
+/// A single VM instruction. Operands are stored inline: constant and name
+/// operands index the chunk's constant pool, jump operands are absolute
+/// instruction indices.
+#[derive(Debug, Clone, Copy)]
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(usize),
+    SetLocal(usize),
+    GetGlobal(usize),
+    DefineGlobal(usize),
+    SetGlobal(usize),
+    GetUpvalue(usize),
+    SetUpvalue(usize),
+    GetProperty(usize),
+    SetProperty(usize),
+    GetSuper(usize),
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Remainder,
+    Not,
+    Negate,
+    Stringify,
+    Print,
+    Jump(usize),
+    JumpIfFalse(usize),
+    Loop(usize),
+    Call(usize),
+    Closure(usize),
+    CloseUpvalue,
+    Return,
+    Class(usize),
+    Inherit,
+    Method(usize),
+}
+
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Int(i64),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<FunctionProto>),
+}
+
+/// A compiled sequence of instructions. `spans[i]` is the source location of
+/// `code[i]`, used to report runtime errors.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Constant>,
+}
+
+impl Chunk {
+    #[inline]
+    fn write(&mut self, op: OpCode, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    #[inline]
+    fn add_constant(&mut self, constant: Constant) -> usize {
+        self.constants.push(constant);
+        self.constants.len() - 1
+    }
+}
+
+/// Where a closure finds a captured variable when it is created: a local slot
+/// of the enclosing function, or one of the enclosing closure's upvalues.
+#[derive(Debug, Clone, Copy)]
+pub struct UpvalueRef {
+    pub is_local: bool,
+    pub index: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct FunctionProto {
+    pub name: Option<String>,
+    pub arity: usize,
+    pub chunk: Chunk,
+    pub upvalues: Vec<UpvalueRef>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    name: Symbol,
+    depth: usize,
+    is_captured: bool,
+}
+
+struct FunctionCompiler {
+    function: FunctionProto,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    scope_depth: usize,
+}
+
+impl FunctionCompiler {
+    fn new(name: Option<String>, kind: FunctionKind) -> FunctionCompiler {
+        // Slot 0 holds the callee, or the receiver inside methods.
+        let receiver = match kind {
+            FunctionKind::Method | FunctionKind::Initializer => Symbol::THIS,
+            FunctionKind::Script | FunctionKind::Function => Symbol::EMPTY,
+        };
+
+        FunctionCompiler {
+            function: FunctionProto { name, ..Default::default() },
+            kind,
+            locals: vec![Local { name: receiver, depth: 0, is_captured: false }],
+            scope_depth: 0,
+        }
+    }
+
+    fn resolve_local(&self, name: Symbol) -> Option<usize> {
+        self.locals.iter().rposition(|local| local.name == name)
+    }
+
+    fn add_upvalue(&mut self, upvalue: UpvalueRef) -> usize {
+        let existing = self.function.upvalues
+            .iter()
+            .position(|u| u.is_local == upvalue.is_local && u.index == upvalue.index);
+
+        match existing {
+            Some(index) => index,
+            None => {
+                self.function.upvalues.push(upvalue);
+                self.function.upvalues.len() - 1
+            }
+        }
+    }
+}
+
+/// Lowers the `Ast` produced by `Parser::parse` into a `FunctionProto`
+/// for the top-level script, ready to be run by `Vm::interpret`.
+pub struct Compiler {
+    frames: Vec<FunctionCompiler>,
+    span: Span,
+}
+
+impl Default for Compiler {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    #[inline]
+    pub fn new() -> Compiler {
+        Compiler { frames: Vec::new(), span: Span::default() }
+    }
+
+    pub fn compile(&mut self, ast: &Ast) -> Result<Rc<FunctionProto>, LoxError> {
+        self.frames.push(FunctionCompiler::new(None, FunctionKind::Script));
+
+        let result = ast.statements().iter().try_for_each(|&stmt| self.execute(ast, stmt));
+        self.emit_return();
+        let frame = self.frames.pop().unwrap();
+
+        result.map(|_| Rc::new(frame.function))
+    }
+
+    #[inline]
+    fn frame(&mut self) -> &mut FunctionCompiler {
+        self.frames.last_mut().unwrap()
+    }
+
+    #[inline]
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.frame().function.chunk
+    }
+
+    #[inline]
+    fn emit(&mut self, op: OpCode) -> usize {
+        let span = self.span;
+        self.chunk().write(op, span)
+    }
+
+    #[inline]
+    fn at(&mut self, token: &Token) {
+        self.span = token.span;
+    }
+
+    fn emit_return(&mut self) {
+        if self.frame().kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal(0));
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    /// Points the jump at `index` to the next instruction to be emitted.
+    fn patch_jump(&mut self, index: usize) {
+        let target = self.chunk().code.len();
+        match &mut self.chunk().code[index] {
+            OpCode::Jump(offset) | OpCode::JumpIfFalse(offset) => *offset = target,
+            _ => unreachable!("only jumps can be patched")
+        }
+    }
+
+    #[inline]
+    fn name_constant(&mut self, name: Symbol) -> usize {
+        self.chunk().add_constant(Constant::String(name.as_rc()))
+    }
+
+    #[inline]
+    fn begin_scope(&mut self) {
+        self.frame().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.frame().scope_depth -= 1;
+
+        loop {
+            let frame = self.frame();
+            let captured = match frame.locals.last() {
+                Some(local) if local.depth > frame.scope_depth => local.is_captured,
+                _ => break
+            };
+            frame.locals.pop();
+
+            self.emit(if captured { OpCode::CloseUpvalue } else { OpCode::Pop });
+        }
+    }
+
+    fn add_local(&mut self, name: Symbol) {
+        let frame = self.frame();
+        let depth = frame.scope_depth;
+        frame.locals.push(Local { name, depth, is_captured: false });
+    }
+
+    /// Binds the value on top of the stack to `name`: as a new local slot
+    /// inside a scope, or as a global at the top level.
+    fn define_variable(&mut self, name: Symbol) {
+        if self.frame().scope_depth > 0 {
+            self.add_local(name);
+        } else {
+            let index = self.name_constant(name);
+            self.emit(OpCode::DefineGlobal(index));
+        }
+    }
+
+    fn resolve_upvalue(&mut self, frame: usize, name: Symbol) -> Option<usize> {
+        if frame == 0 {
+            return None;
+        }
+
+        if let Some(index) = self.frames[frame - 1].resolve_local(name) {
+            self.frames[frame - 1].locals[index].is_captured = true;
+            return Some(self.frames[frame].add_upvalue(UpvalueRef { is_local: true, index }));
+        }
+
+        let index = self.resolve_upvalue(frame - 1, name)?;
+        Some(self.frames[frame].add_upvalue(UpvalueRef { is_local: false, index }))
+    }
+
+    fn named_variable(&mut self, name: Symbol, assign: bool) {
+        let top = self.frames.len() - 1;
+
+        let op = if let Some(slot) = self.frames[top].resolve_local(name) {
+            if assign { OpCode::SetLocal(slot) } else { OpCode::GetLocal(slot) }
+        } else if let Some(index) = self.resolve_upvalue(top, name) {
+            if assign { OpCode::SetUpvalue(index) } else { OpCode::GetUpvalue(index) }
+        } else {
+            let index = self.name_constant(name);
+            if assign { OpCode::SetGlobal(index) } else { OpCode::GetGlobal(index) }
+        };
+
+        self.emit(op);
+    }
+
+    fn function(&mut self, ast: &Ast, name: &Token, params: &[TokenId], body: &[StmtId], kind: FunctionKind) -> Result<(), LoxError> {
+        self.frames.push(FunctionCompiler::new(name.lexeme.map(|name| name.to_string()), kind));
+        self.frame().function.arity = params.len();
+
+        self.begin_scope();
+        for &param in params {
+            self.add_local(ast.token(param).name());
+        }
+
+        let result = body.iter().try_for_each(|&stmt| self.execute(ast, stmt));
+        self.at(name);
+        self.emit_return();
+        let frame = self.frames.pop().unwrap();
+        result?;
+
+        let index = self.chunk().add_constant(Constant::Function(Rc::new(frame.function)));
+        self.emit(OpCode::Closure(index));
+        Ok(())
+    }
+}
+
+impl VisitorExpr<()> for Compiler {
+    fn visit_assign_expr(&mut self, ast: &Ast, _id: ExprId, name: &Token, value: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, value)?;
+        self.at(name);
+        self.named_variable(name.name(), true);
+        Ok(())
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, left)?;
+        self.evaluate(ast, right)?;
+        self.at(operator);
+
+        let op = match operator.r#type {
+            TokenType::BangEqual => OpCode::NotEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Plus => OpCode::Add,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::SlashSlash => OpCode::IntDivide,
+            TokenType::Percent => OpCode::Remainder,
+            _ => {
+                self.emit(OpCode::Pop);
+                self.emit(OpCode::Pop);
+                OpCode::Nil
+            }
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> Result<(), LoxError> {
+        self.evaluate(ast, callee)?;
+
+        let count = arguments.len();
+        for &argument in arguments {
+            self.evaluate(ast, argument)?;
+        }
+
+        self.at(paren);
+        self.emit(OpCode::Call(count));
+        Ok(())
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, object: ExprId, name: &Token) -> Result<(), LoxError> {
+        self.evaluate(ast, object)?;
+        self.at(name);
+        let index = self.name_constant(name.name());
+        self.emit(OpCode::GetProperty(index));
+        Ok(())
+    }
+
+    #[inline]
+    fn visit_grouping_expr(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)
+    }
+
+    fn visit_literal_expr(&mut self, _ast: &Ast, value: &LoxLiteral) -> Result<(), LoxError> {
+        match value {
+            LoxLiteral::String(str) => {
+                let index = self.chunk().add_constant(Constant::String(str.as_rc()));
+                self.emit(OpCode::Constant(index));
+            }
+            LoxLiteral::Int(int) => {
+                let index = self.chunk().add_constant(Constant::Int(*int));
+                self.emit(OpCode::Constant(index));
+            }
+            LoxLiteral::Number(num) => {
+                let index = self.chunk().add_constant(Constant::Number(*num));
+                self.emit(OpCode::Constant(index));
+            }
+            LoxLiteral::Bool(true) => { self.emit(OpCode::True); }
+            LoxLiteral::Bool(false) => { self.emit(OpCode::False); }
+            LoxLiteral::Nil => { self.emit(OpCode::Nil); }
+        }
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, left)?;
+        self.at(operator);
+
+        if operator.r#type == TokenType::Or {
+            let else_jump = self.emit(OpCode::JumpIfFalse(0));
+            let end_jump = self.emit(OpCode::Jump(0));
+            self.patch_jump(else_jump);
+            self.emit(OpCode::Pop);
+            self.evaluate(ast, right)?;
+            self.patch_jump(end_jump);
+        } else {
+            let end_jump = self.emit(OpCode::JumpIfFalse(0));
+            self.emit(OpCode::Pop);
+            self.evaluate(ast, right)?;
+            self.patch_jump(end_jump);
+        }
+        Ok(())
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, object: ExprId, name: &Token, value: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, object)?;
+        self.evaluate(ast, value)?;
+        self.at(name);
+        let index = self.name_constant(name.name());
+        self.emit(OpCode::SetProperty(index));
+        Ok(())
+    }
+
+    fn visit_super_expr(&mut self, _ast: &Ast, _id: ExprId, keyword: &Token, method: &Token) -> Result<(), LoxError> {
+        self.at(keyword);
+        self.named_variable(Symbol::THIS, false);
+        self.named_variable(Symbol::SUPER, false);
+        self.at(method);
+        let index = self.name_constant(method.name());
+        self.emit(OpCode::GetSuper(index));
+        Ok(())
+    }
+
+    fn visit_this_expr(&mut self, _ast: &Ast, _id: ExprId, keyword: &Token) -> Result<(), LoxError> {
+        self.at(keyword);
+        self.named_variable(Symbol::THIS, false);
+        Ok(())
+    }
+
+    fn visit_unary_expr(&mut self, ast: &Ast, operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, right)?;
+        self.at(operator);
+
+        match operator.r#type {
+            TokenType::Bang => { self.emit(OpCode::Not); }
+            TokenType::Minus => { self.emit(OpCode::Negate); }
+            TokenType::Interpolation => { self.emit(OpCode::Stringify); }
+            _ => {
+                self.emit(OpCode::Pop);
+                self.emit(OpCode::Nil);
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_variable_expr(&mut self, _ast: &Ast, _id: ExprId, name: &Token) -> Result<(), LoxError> {
+        self.at(name);
+        self.named_variable(name.name(), false);
+        Ok(())
+    }
+}
+
+impl VisitorStmt<()> for Compiler {
+    fn visit_block_stmt(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<(), LoxError> {
+        self.begin_scope();
+        let result = statements.iter().try_for_each(|&stmt| self.execute(ast, stmt));
+        self.end_scope();
+        result
+    }
+
+    fn visit_class_stmt(&mut self, ast: &Ast, name: &Token, superclass: Option<ExprId>, methods: &[StmtId]) -> Result<(), LoxError> {
+        let class_name = name.name();
+
+        self.at(name);
+        let index = self.name_constant(class_name);
+        self.emit(OpCode::Class(index));
+        self.define_variable(class_name);
+
+        let has_superclass = superclass.is_some();
+        if let Some(superclass) = superclass {
+            self.evaluate(ast, superclass)?;
+            self.begin_scope();
+            self.add_local(Symbol::SUPER);
+
+            self.at(name);
+            self.named_variable(class_name, false);
+            // Errors point at the superclass, as in the interpreter.
+            if let Expr::Variable { name } = ast.expr(superclass) {
+                self.at(ast.token(*name));
+            }
+            self.emit(OpCode::Inherit);
+        }
+
+        self.at(name);
+        self.named_variable(class_name, false);
+
+        for &method in methods {
+            if let Stmt::Function { name, params, body } = ast.stmt(method) {
+                let name = ast.token(*name);
+                let method_name = name.name();
+                let kind = if method_name == Symbol::INIT { FunctionKind::Initializer } else { FunctionKind::Method };
+
+                self.function(ast, name, params, body, kind)?;
+                self.at(name);
+                let index = self.name_constant(method_name);
+                self.emit(OpCode::Method(index));
+            }
+        }
+
+        self.emit(OpCode::Pop);
+
+        if has_superclass {
+            self.end_scope();
+        }
+        Ok(())
+    }
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)?;
+        self.emit(OpCode::Pop);
+        Ok(())
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, name: &Token, params: &[TokenId], body: &[StmtId]) -> Result<(), LoxError> {
+        let lexeme = name.name();
+
+        // A local function is in scope inside its own body so it can recurse.
+        if self.frame().scope_depth > 0 {
+            self.add_local(lexeme);
+            self.function(ast, name, params, body, FunctionKind::Function)?;
+        } else {
+            self.function(ast, name, params, body, FunctionKind::Function)?;
+            self.at(name);
+            self.define_variable(lexeme);
+        }
+        Ok(())
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Result<(), LoxError> {
+        self.evaluate(ast, condition)?;
+
+        let then_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        self.execute(ast, then_branch)?;
+
+        let else_jump = self.emit(OpCode::Jump(0));
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+
+        if let Some(else_branch) = else_branch {
+            self.execute(ast, else_branch)?;
+        }
+        self.patch_jump(else_jump);
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)?;
+        self.emit(OpCode::Print);
+        Ok(())
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, keyword: &Token, value: Option<ExprId>) -> Result<(), LoxError> {
+        match value {
+            Some(expr) => {
+                self.evaluate(ast, expr)?;
+                self.at(keyword);
+                self.emit(OpCode::Return);
+            }
+            None => {
+                self.at(keyword);
+                self.emit_return();
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, name: &Token, initializer: Option<ExprId>) -> Result<(), LoxError> {
+        match initializer {
+            Some(expr) => self.evaluate(ast, expr)?,
+            None => { self.emit(OpCode::Nil); }
+        }
+
+        self.at(name);
+        self.define_variable(name.name());
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, condition: ExprId, body: StmtId) -> Result<(), LoxError> {
+        let loop_start = self.chunk().code.len();
+        self.evaluate(ast, condition)?;
+
+        let exit_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        self.execute(ast, body)?;
+        self.emit(OpCode::Loop(loop_start));
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+        Ok(())
+    }
+}
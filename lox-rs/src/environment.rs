@@ -1,49 +1,107 @@
 use std::{collections::HashMap, rc::Rc, cell::RefCell};
 
-use crate::{ast::LoxObject, error::LoxError, token::Token};
+use crate::{ast::{Local, LoxObject}, error::LoxError, gc::{Node, Trace}, intern::Symbol, token::Token, value::{Slot, Value}};
 
-#[derive(Clone)]
+/// Variables declared at the top level. They are looked up by name when the
+/// code runs, so a function may use a global that is only defined later.
+pub struct Globals {
+    values: HashMap<Symbol, Value>
+}
+
+impl Globals {
+    pub fn new() -> Globals {
+        Globals { values: HashMap::new() }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<LoxObject, LoxError> {
+        match self.values.get(&name.name()) {
+            Some(value) => Ok(value.load()),
+            None => Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", name.name()).into(), span: name.span })
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: LoxObject) {
+        self.values.insert(name, Value::store(value));
+    }
+
+    pub fn assign(&mut self, name: &Token, value: LoxObject) -> Result<LoxObject, LoxError> {
+        match self.values.get_mut(&name.name()) {
+            Some(slot) => {
+                *slot = Value::store(value.clone());
+                Ok(value)
+            }
+            None => Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", name.name()).into(), span: name.span })
+        }
+    }
+}
+
+/// One local scope. Its variables sit in the slots the resolver gave them,
+/// which are filled in declaration order by `define`.
 pub struct Environment {
-    values: HashMap<String, LoxObject>,
+    values: Vec<Value>,
     environment: Option<Rc<RefCell<Environment>>>
 }
 
 impl Environment {
-    pub fn new() -> Environment {
-        Environment { values: HashMap::new(), environment: None }
+    /// A new scope nested in `environment`, or directly under the globals.
+    pub fn from(environment: Option<Rc<RefCell<Environment>>>) -> Environment {
+        Environment { values: Vec::new(), environment }
     }
 
-    pub fn from(environment: Rc<RefCell<Environment>>) -> Environment {
-        Environment { values: HashMap::new(), environment: Some(environment) }
+    /// Reads the variable the resolver placed at `local`, counting scopes out
+    /// from this one.
+    pub fn get_at(&self, local: Local, name: &Token) -> Result<LoxObject, LoxError> {
+        if local.depth == 0 {
+            return match self.values.get(local.slot) {
+                Some(value) => Ok(value.load()),
+                None => Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", name.name()).into(), span: name.span })
+            };
+        }
+
+        match &self.environment {
+            Some(environment) => environment.borrow().get_at(Local { depth: local.depth - 1, ..local }, name),
+            None => Err(LoxError::RuntimeError { msg: "Resolved scope is out of range.".into(), span: name.span })
+        }
     }
 
-    pub fn get(&self, name: Token) -> Result<LoxObject, LoxError> {
-        let lexeme = name.clone().lexeme.unwrap();
-        if self.values.contains_key(&lexeme) {
-            Ok(self.values.get(&lexeme).unwrap().clone())
-        } else {
-            if let Some(environment) = &self.environment {
-                environment.borrow().get(name)
-            } else {
-                Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", &lexeme).into(), line: name.line })
-            }
+    pub fn assign_at(&mut self, local: Local, name: &Token, value: LoxObject) -> Result<LoxObject, LoxError> {
+        if local.depth == 0 {
+            return match self.values.get_mut(local.slot) {
+                Some(slot) => {
+                    *slot = Value::store(value.clone());
+                    Ok(value)
+                }
+                None => Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", name.name()).into(), span: name.span })
+            };
+        }
+
+        match &self.environment {
+            Some(environment) => environment.borrow_mut().assign_at(Local { depth: local.depth - 1, ..local }, name, value),
+            None => Err(LoxError::RuntimeError { msg: "Resolved scope is out of range.".into(), span: name.span })
         }
     }
 
-    pub fn define(&mut self, name: String, value: LoxObject) {
-        self.values.insert(name, value);
+    #[inline]
+    pub fn get_here(&self, slot: usize) -> Option<LoxObject> {
+        self.values.get(slot).map(Slot::load)
     }
 
-    pub fn assign(&mut self, name: Token, value: LoxObject) -> Result<LoxObject, LoxError> {
-        let lexeme = name.clone().lexeme.unwrap();
-        if self.values.contains_key(&lexeme) {
-            Ok(self.values.insert(lexeme, value).unwrap())
-        } else {
-            if let Some(environment) = &mut self.environment {
-                environment.borrow_mut().assign(name, value)
-            } else {
-                Err(LoxError::RuntimeError { msg: format!("Undefined variable '{}'.", lexeme).into(), line: name.line })   
-            }
-        }
-    }   
-}
\ No newline at end of file
+    /// Fills the next slot of this scope.
+    #[inline]
+    pub fn define(&mut self, value: LoxObject) {
+        self.values.push(Value::store(value));
+    }
+
+    /// Empties a scope the cycle collector found unreachable.
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+        self.environment = None;
+    }
+}
+
+impl Trace for Environment {
+    fn trace(&self, children: &mut Vec<Node>) {
+        children.extend(self.values.iter().filter_map(|value| Node::from_object(value.load())));
+        children.extend(self.environment.clone().map(Node::Environment));
+    }
+}
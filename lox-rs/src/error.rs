@@ -1,33 +1,113 @@
 use std::fmt::Display;
 
+use crate::token::Span;
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum LoxError {
     LexError {
         char: char,
         msg: Box<str>,
-        line: u16
+        span: Span
     },
     ParseError {
         msg: Box<str>,
-        line: u16
+        span: Span
+    },
+    ResolveError {
+        msg: Box<str>,
+        span: Span
     },
     RuntimeError {
         msg: Box<str>,
-        line: u16
+        span: Span
     }
 }
 
-impl Display for LoxError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl LoxError {
+    #[inline]
+    pub fn span(&self) -> Span {
+        match self {
+            LoxError::LexError { span, .. }
+            | LoxError::ParseError { span, .. }
+            | LoxError::ResolveError { span, .. }
+            | LoxError::RuntimeError { span, .. } => *span
+        }
+    }
+
+    #[inline]
+    fn kind(&self) -> &'static str {
+        match self {
+            LoxError::LexError { .. } => "LexError",
+            LoxError::ParseError { .. } => "ParseError",
+            LoxError::ResolveError { .. } => "ResolveError",
+            LoxError::RuntimeError { .. } => "RunTimeError"
+        }
+    }
+
+    #[inline]
+    fn message(&self) -> String {
         match self {
-            LoxError::LexError { char, msg, line } => {
-                if *char == ' ' {
-                    return write!(f, "[line {}] LexError {}", line, msg)
-                }
-                write!(f, "[line {}] LexError `{}` {}", line, char, msg)
-            }
-            LoxError::ParseError { msg, line } => write!(f, "[line {}] ParseError {}", line, msg),
-            LoxError::RuntimeError { msg, line } => write!(f, "[line {}] RunTimeError {}", line, msg)
+            LoxError::LexError { char, msg, .. } if *char != ' ' => format!("`{}` {}", char, msg),
+            LoxError::LexError { msg, .. }
+            | LoxError::ParseError { msg, .. }
+            | LoxError::ResolveError { msg, .. }
+            | LoxError::RuntimeError { msg, .. } => msg.to_string()
         }
     }
-}
\ No newline at end of file
+
+    /// Renders the error in the style of rustc, quoting the offending source
+    /// line and underlining the span with carets:
+    ///
+    /// ```text
+    /// ParseError: Expect expression.
+    ///  --> 2:10
+    ///   |
+    /// 2 | print 1 +;
+    ///   |          ^
+    /// ```
+    ///
+    /// Falls back to the plain `[line N]` format when the line is not in `source`.
+    ///
+    /// `source` is the raw program, since spans are byte offsets into it.
+    /// Invalid UTF-8 is shown as `U+FFFD`, one column per invalid sequence,
+    /// the way the scanner counts it.
+    pub fn render(&self, source: &[u8]) -> String {
+        let span = self.span();
+
+        let line = (span.line as usize).checked_sub(1).and_then(|i| source.split(|&b| b == b'\n').nth(i));
+        let text = match line {
+            Some(line) => String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)),
+            None => return self.to_string()
+        };
+
+        let gutter = span.line.to_string();
+        let pad = " ".repeat(gutter.len());
+
+        // Only the part of the span on its first line is underlined.
+        let offset = text.char_indices().nth(span.column.saturating_sub(1) as usize).map_or(text.len(), |(i, _)| i);
+        let width = source
+            .get(span.start..span.end)
+            .and_then(|lexeme| lexeme.split(|&b| b == b'\n').next())
+            .map_or(0, |lexeme| String::from_utf8_lossy(lexeme).chars().count())
+            .min(text[offset..].chars().count())
+            .max(1);
+
+        let indent = text[..offset].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect::<String>();
+
+        format!(
+            "{}: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+            self.kind(), self.message(),
+            pad, span.line, span.column,
+            pad,
+            gutter, text,
+            pad, indent, "^".repeat(width)
+        )
+    }
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] {} {}", self.span().line, self.kind(), self.message())
+    }
+}
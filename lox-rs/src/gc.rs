@@ -0,0 +1,260 @@
+//! Cycle collection for the tree-walking interpreter.
+//!
+//! Values are reference counted, which frees almost everything on time but
+//! never frees a cycle: a function stored in the environment it closes over,
+//! an instance holding one of its own bound methods, `this.me = this`. Every
+//! such cycle passes through an instance or through an environment some
+//! function closes over, so those are the objects the collector keeps track
+//! of. Scopes that are never captured, like most blocks and calls, cost it
+//! nothing.
+//!
+//! A collection uses trial deletion. Starting from every tracked object, it
+//! walks the references between environments, instances, functions and
+//! classes and subtracts them from each object's reference count. Whatever
+//! still has references left is held from outside the heap (a global, the
+//! interpreter, a Rust local) and is kept together with everything it reaches.
+//! The rest is garbage: its environments and instances are emptied, which
+//! breaks the cycles and lets reference counting free them. Because nothing
+//! needs to enumerate roots, a collection is safe at any point, so one runs
+//! automatically every time enough new objects have been tracked.
+
+use std::{cell::{Cell, RefCell}, collections::{HashMap, HashSet}, rc::{Rc, Weak}};
+
+use crate::{ast::LoxObject, callable::LoxFunction, class::{LoxClass, LoxInstance}, environment::Environment};
+
+// Objects tracked before the first automatic collection. Afterwards the limit
+// is twice what survived the last one, so a growing heap isn't rescanned on
+// every allocation.
+const INITIAL_THRESHOLD: usize = 10_000;
+
+/// Counters for checking that a program doesn't leak.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HeapStats {
+    /// Captured environments that are still allocated.
+    pub environments: usize,
+    /// Instances that are still allocated.
+    pub instances: usize,
+    /// Collections run so far, automatic or not.
+    pub collections: usize,
+    /// Objects freed by the collector so far. Objects freed by reference
+    /// counting alone aren't counted.
+    pub freed: usize
+}
+
+/// An object the collector can walk through.
+pub(crate) enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>)
+}
+
+impl Node {
+    /// The object a value refers to, if it can be part of a cycle.
+    pub(crate) fn from_object(value: LoxObject) -> Option<Node> {
+        match value {
+            LoxObject::Function(function) => Some(Node::Function(function)),
+            LoxObject::Class(class) => Some(Node::Class(class)),
+            LoxObject::Instance(instance) => Some(Node::Instance(instance)),
+            _ => None
+        }
+    }
+
+    fn address(&self) -> usize {
+        match self {
+            Node::Environment(environment) => Rc::as_ptr(environment) as *const () as usize,
+            Node::Instance(instance) => Rc::as_ptr(instance) as *const () as usize,
+            Node::Function(function) => Rc::as_ptr(function) as *const () as usize,
+            Node::Class(class) => Rc::as_ptr(class) as *const () as usize
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Environment(environment) => Rc::strong_count(environment),
+            Node::Instance(instance) => Rc::strong_count(instance),
+            Node::Function(function) => Rc::strong_count(function),
+            Node::Class(class) => Rc::strong_count(class)
+        }
+    }
+
+    /// Pushes the objects this one references. Returns `false` if it is
+    /// borrowed for writing and can't be inspected right now.
+    fn trace(&self, children: &mut Vec<Node>) -> bool {
+        match self {
+            Node::Environment(environment) => environment.try_borrow().map(|environment| environment.trace(children)).is_ok(),
+            Node::Instance(instance) => instance.try_borrow().map(|instance| instance.trace(children)).is_ok(),
+            Node::Function(function) => {
+                function.trace(children);
+                true
+            }
+            Node::Class(class) => {
+                class.trace(children);
+                true
+            }
+        }
+    }
+
+    /// Drops the references this object holds, which breaks any cycle it is
+    /// part of.
+    fn clear(&self) {
+        match self {
+            Node::Environment(environment) => environment.borrow_mut().clear(),
+            Node::Instance(instance) => instance.borrow_mut().clear(),
+            Node::Function(_) | Node::Class(_) => ()
+        }
+    }
+}
+
+/// Implemented by every type that can hold a `Node`.
+pub(crate) trait Trace {
+    fn trace(&self, children: &mut Vec<Node>);
+}
+
+struct Heap {
+    environments: Vec<Weak<RefCell<Environment>>>,
+    instances: Vec<Weak<RefCell<LoxInstance>>>,
+    threshold: usize,
+    collections: usize,
+    freed: usize
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const { RefCell::new(Heap {
+        environments: Vec::new(),
+        instances: Vec::new(),
+        threshold: INITIAL_THRESHOLD,
+        collections: 0,
+        freed: 0
+    }) };
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Starts tracking an environment a function closes over. An environment
+/// captured by several functions may be tracked more than once.
+pub(crate) fn track_environment(environment: &Rc<RefCell<Environment>>) {
+    HEAP.with(|heap| heap.borrow_mut().environments.push(Rc::downgrade(environment)));
+    maybe_collect();
+}
+
+/// Starts tracking a new instance.
+pub(crate) fn track_instance(instance: &Rc<RefCell<LoxInstance>>) {
+    HEAP.with(|heap| heap.borrow_mut().instances.push(Rc::downgrade(instance)));
+    maybe_collect();
+}
+
+fn maybe_collect() {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.environments.len() + heap.instances.len() >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+/// Frees every unreachable cycle of environments and instances created on
+/// this thread, and returns how many objects that freed.
+pub fn collect() -> usize {
+    if COLLECTING.with(|collecting| collecting.replace(true)) {
+        return 0;
+    }
+
+    // Start from every tracked object that is still alive.
+    let tracked = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.environments.retain(|environment| environment.strong_count() > 0);
+        heap.instances.retain(|instance| instance.strong_count() > 0);
+
+        let environments = heap.environments.iter().filter_map(Weak::upgrade).map(Node::Environment);
+        let instances = heap.instances.iter().filter_map(Weak::upgrade).map(Node::Instance);
+        environments.chain(instances).collect::<Vec<_>>()
+    });
+
+    let mut nodes = Vec::with_capacity(tracked.len());
+    let mut index = HashMap::with_capacity(tracked.len());
+    for node in tracked {
+        index.entry(node.address()).or_insert_with(|| {
+            nodes.push(node);
+            nodes.len() - 1
+        });
+    }
+
+    let mut edges: Vec<Vec<usize>> = Vec::new();
+    let mut opaque = Vec::new();
+    let mut children = Vec::new();
+
+    // Discover the functions and classes in between, and every reference
+    // between two nodes.
+    let mut i = 0;
+    while i < nodes.len() {
+        opaque.push(!nodes[i].trace(&mut children));
+
+        let mut targets = Vec::with_capacity(children.len());
+        for child in children.drain(..) {
+            let target = *index.entry(child.address()).or_insert_with(|| {
+                nodes.push(child);
+                nodes.len() - 1
+            });
+            targets.push(target);
+        }
+        edges.push(targets);
+        i += 1;
+    }
+
+    // Trial deletion: take away the references nodes hold to each other, and
+    // the one `nodes` itself holds. Anything left over comes from outside.
+    let mut external = nodes.iter().map(|node| node.strong_count() - 1).collect::<Vec<_>>();
+    for targets in &edges {
+        for &target in targets {
+            external[target] -= 1;
+        }
+    }
+
+    let mut reachable = vec![false; nodes.len()];
+    let mut stack = (0..nodes.len()).filter(|&i| external[i] > 0 || opaque[i]).collect::<Vec<_>>();
+    while let Some(i) = stack.pop() {
+        if !std::mem::replace(&mut reachable[i], true) {
+            stack.extend(&edges[i]);
+        }
+    }
+
+    let mut freed = 0;
+    for (node, _) in nodes.iter().zip(&reachable).filter(|(_, &reachable)| !reachable) {
+        node.clear();
+        freed += 1;
+    }
+
+    drop(nodes);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.environments.retain(|environment| environment.strong_count() > 0);
+        heap.instances.retain(|instance| instance.strong_count() > 0);
+        heap.threshold = INITIAL_THRESHOLD.max((heap.environments.len() + heap.instances.len()) * 2);
+        heap.collections += 1;
+        heap.freed += freed;
+    });
+
+    COLLECTING.with(|collecting| collecting.set(false));
+    freed
+}
+
+/// How many tracked environments and instances are alive on this thread, and what
+/// the collector has done so far.
+pub fn stats() -> HeapStats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        HeapStats {
+            environments: heap.environments
+                .iter()
+                .filter(|environment| environment.strong_count() > 0)
+                .map(Weak::as_ptr)
+                .collect::<HashSet<_>>()
+                .len(),
+            instances: heap.instances.iter().filter(|instance| instance.strong_count() > 0).count(),
+            collections: heap.collections,
+            freed: heap.freed
+        }
+    })
+}
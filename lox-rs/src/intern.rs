@@ -0,0 +1,82 @@
+use std::{cell::RefCell, collections::HashMap, fmt::{Debug, Display}, rc::Rc};
+
+use crate::ast::LoxStr;
+
+/// A handle to an interned string. Two symbols are equal exactly when their
+/// strings are, so environments and property tables can hash and compare a
+/// `u32` instead of the bytes.
+///
+/// The interner is per thread and never forgets a string; only identifiers and
+/// string literals from source code are interned, so it grows with the size
+/// of the programs loaded rather than with what they compute.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Symbol(u32);
+
+// Interned up front, in this order, so the names the runtime looks up
+// implicitly are constants.
+const PREDEFINED: [&str; 4] = ["", "this", "super", "init"];
+
+impl Symbol {
+    pub const EMPTY: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+    pub const INIT: Symbol = Symbol(3);
+
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(name))
+    }
+
+    /// The interned string. Cloning it only bumps a reference count.
+    pub fn as_rc(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::<str>::clone(&interner.borrow().strings[self.0 as usize]))
+    }
+
+    /// The interned string as a Lox string value, shared by every evaluation
+    /// of the literal it came from.
+    pub(crate) fn as_value(self) -> LoxStr {
+        INTERNER.with(|interner| interner.borrow().strings[self.0 as usize].clone())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_rc())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_rc())
+    }
+}
+
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<LoxStr>
+}
+
+impl Interner {
+    fn new() -> Interner {
+        let mut interner = Interner { symbols: HashMap::new(), strings: Vec::new() };
+        for name in PREDEFINED {
+            interner.intern(name);
+        }
+        interner
+    }
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+
+        let symbol = Symbol(u32::try_from(self.strings.len()).expect("too many interned strings"));
+        let name: Rc<str> = name.into();
+        self.strings.push(LoxStr::from(name.clone()));
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
@@ -1,222 +1,363 @@
-use std::{rc::Rc, cell::RefCell};
+use std::{rc::Rc, cell::RefCell, cmp::Ordering, collections::HashMap, time::{SystemTime, UNIX_EPOCH}};
 
 use crate::{
-    ast::{VisitorExpr, Expr, LoxObject, LoxLiteral, VisitorStmt, Stmt},
+    ast::{walk_expr, walk_stmt, Ast, VisitorExpr, Expr, ExprId, Local, LoxObject, LoxLiteral, VisitorStmt, Stmt, StmtId, TokenId},
     error::LoxError,
-    token::{Token, TokenType}, environment::Environment,
+    intern::Symbol,
+    number::Operator,
+    primitive::{is_equal, is_truthy, stringify},
+    token::{Span, Token, TokenType}, environment::{Environment, Globals},
+    callable::{LoxCallable, LoxFunction, LoxNative},
+    class::{LoxClass, LoxInstance},
 };
 
+// Calls that may be in progress at once, the same limit as the VM's
+// `FRAMES_MAX`.
+const MAX_CALL_DEPTH: usize = 1024;
+
+// Statements and expressions that may be executing at once, counting every
+// enclosing block, loop and call. Each one takes a few Rust stack frames, so
+// without a limit deep recursion through nested code would overflow the
+// native stack and abort.
+const MAX_NESTING: usize = 65_536;
+
+/// The native stack the interpreter needs to reach `MAX_NESTING` in a debug
+/// build, with room to spare. Run it on a thread with at least this much.
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// How a statement finished executing. `Return` unwinds through enclosing
+/// blocks and loops until it reaches the call boundary in `LoxFunction::call`.
+pub(crate) enum Completion {
+    Normal,
+    Return(LoxObject)
+}
+
 pub struct Interpreter {
-    environment: Rc<RefCell<Environment>>
+    globals: Globals,
+    // The innermost local scope, or `None` while running top-level code.
+    environment: Option<Rc<RefCell<Environment>>>,
+    // The program currently executing; functions declared while it runs keep
+    // a handle to it so their bodies outlive the call to `interpret`.
+    ast: Rc<Ast>,
+    // Calls currently in progress.
+    depth: usize,
+    // Statements and expressions currently executing.
+    nesting: usize,
+    // The innermost call in progress, where running out of stack is reported.
+    call: Span
+}
+
+impl Default for Interpreter {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     #[inline]
     pub fn new() -> Interpreter {
-        Interpreter { environment: Rc::new(RefCell::new(Environment::new())) }
+        let mut interpreter = Interpreter { globals: Globals::new(), environment: None, ast: Rc::new(Ast::default()), depth: 0, nesting: 0, call: Span::default() };
+
+        interpreter.define_native("clock", 0, |_| {
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+            Ok(LoxObject::Number(elapsed.as_secs_f64()))
+        });
+
+        interpreter
+    }
+
+    /// Exposes a host function to scripts as a global named `name`. Scripts
+    /// must call it with exactly `arity` arguments; an `Err` returned by
+    /// `function` becomes a runtime error at the call site.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&[LoxObject]) -> Result<LoxObject, String> + 'static
+    {
+        let native = LoxNative::new(name.to_owned(), arity, function);
+        self.globals.define(Symbol::intern(name), LoxObject::Native(Rc::new(native)));
     }
 
     #[inline]
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), LoxError> {
-        for stmt in statements {
-            self.execute(stmt)?;
+    pub fn interpret(&mut self, ast: Ast) -> Result<(), LoxError> {
+        let ast = Rc::new(ast);
+        self.ast = ast.clone();
+
+        for &stmt in ast.statements() {
+            self.execute(&ast, stmt)?;
         }
         
         Ok(())
     }
     
-    #[inline]
-    fn is_truthy(&self, value: &LoxObject) -> bool {
-        match value {
-            LoxObject::Nil => false,
-            LoxObject::Bool(bool) => *bool,
-            _ => true        
+    fn comparison(&self, left: &LoxObject, right: &LoxObject, operator: &Token, test: fn(Ordering) -> bool) -> Result<LoxObject, LoxError> {
+        match (left.as_number(), right.as_number()) {
+            // NaN is neither less nor greater than anything.
+            (Some(l), Some(r)) => Ok(LoxObject::Bool(l.compare(r).is_some_and(test))),
+            _ => {
+                let msg = format!("{} and {} must be numbers.", left, right);
+                Err(LoxError::RuntimeError { msg: msg.into(), span: operator.span })
+            }
         }
     }
 
-    #[inline]
-    fn is_equal(&self, l: &LoxObject, r: &LoxObject) -> bool {
-        match (l, r) {
-            (LoxObject::Nil, LoxObject::Nil) => true,
-            (LoxObject::Bool(l), LoxObject::Bool(r)) => l == r,
-            (LoxObject::String(l), LoxObject::String(r)) => l == r,
-            (LoxObject::Number(l), LoxObject::Number(r)) => l == r,
-            _ => false
+    fn arithmetic(&self, left: &LoxObject, right: &LoxObject, operator: &Token, op: Operator) -> Result<LoxObject, LoxError> {
+        match (left.as_number(), right.as_number()) {
+            (Some(l), Some(r)) => l
+                .arithmetic(op, r)
+                .map(LoxObject::from)
+                .map_err(|msg| LoxError::RuntimeError { msg: msg.into(), span: operator.span }),
+            _ => {
+                let msg = format!("{} and {} must be numbers.", left, right);
+                Err(LoxError::RuntimeError { msg: msg.into(), span: operator.span })
+            }
         }
     }
 
+    fn look_up_variable(&self, ast: &Ast, id: ExprId, name: &Token) -> Result<LoxObject, LoxError> {
+        match ast.local(id) {
+            Some(local) => self.scope(name)?.borrow().get_at(local, name),
+            None => self.globals.get(name)
+        }
+    }
+
+    /// The innermost local scope, which a resolved variable must live in.
     #[inline]
-    fn stringify(&self, value: LoxObject) -> String {
-        match value {
-            LoxObject::String(str) => str,
-            LoxObject::Number(num) => num.to_string(),
-            LoxObject::Bool(bool) => bool.to_string(),
-            LoxObject::Nil => "nil".to_owned()
+    fn scope(&self, name: &Token) -> Result<&Rc<RefCell<Environment>>, LoxError> {
+        self.environment
+            .as_ref()
+            .ok_or_else(|| LoxError::RuntimeError { msg: "Resolved scope is out of range.".into(), span: name.span })
+    }
+
+    /// Declares `name` in the innermost scope: in its next slot if that is a
+    /// local scope, by name at the top level.
+    fn define(&mut self, name: &Token, value: LoxObject) {
+        match &self.environment {
+            Some(environment) => environment.borrow_mut().define(value),
+            None => self.globals.define(name.name(), value)
         }
     }
 
-    fn execute_block(&mut self, statements: Vec<Stmt>, environment: Rc<RefCell<Environment>>) -> Result<(), LoxError> {
-        let previous = environment.clone();
+    /// Runs a function body that was parsed as part of `ast`, which may be an
+    /// earlier program than the one currently executing.
+    pub(crate) fn execute_body(&mut self, ast: &Rc<Ast>, body: &[StmtId], environment: Rc<RefCell<Environment>>) -> Result<Completion, LoxError> {
+        let previous = std::mem::replace(&mut self.ast, ast.clone());
+        let result = self.execute_block(ast, body, environment);
+        self.ast = previous;
+        result
+    }
+
+    /// Runs `visit` one level deeper, or fails once `MAX_NESTING` levels are
+    /// in use.
+    #[inline]
+    fn nested<R>(&mut self, visit: impl FnOnce(&mut Self) -> Result<R, LoxError>) -> Result<R, LoxError> {
+        if self.nesting >= MAX_NESTING {
+            return Err(LoxError::RuntimeError { msg: "Stack overflow.".into(), span: self.call });
+        }
 
-        self.environment = Rc::new(RefCell::new(Environment::from(environment)));
+        self.nesting += 1;
+        let result = visit(self);
+        self.nesting -= 1;
+        result
+    }
 
-        for stmt in statements {
-            self.execute(stmt)?;
+    fn execute_block(&mut self, ast: &Ast, statements: &[StmtId], environment: Rc<RefCell<Environment>>) -> Result<Completion, LoxError> {
+        let previous = self.environment.replace(environment);
+
+        let mut result = Ok(Completion::Normal);
+        for &stmt in statements {
+            result = self.execute(ast, stmt);
+            if !matches!(result, Ok(Completion::Normal)) {
+                break;
+            }
         }
 
         self.environment = previous;
 
-        Ok(())
+        result
     }
 }
 
 impl VisitorExpr<LoxObject> for Interpreter {
-    fn visit_assign_expr(&mut self, name: Token, value: Box<Expr>) -> Result<LoxObject, LoxError> {
-        let value = self.evaluate(*value)?;
-        self.environment.borrow_mut().assign(name, value)
+    #[inline]
+    fn evaluate(&mut self, ast: &Ast, expr: ExprId) -> Result<LoxObject, LoxError> {
+        self.nested(|interpreter| walk_expr(interpreter, ast, expr))
     }
 
-    fn visit_binary_expr(&mut self, left: Box<Expr>, operator: Token, right: Box<Expr>) -> Result<LoxObject, LoxError> {
-        let left = self.evaluate(*left)?;
-        let right = self.evaluate(*right)?;
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, name: &Token, value: ExprId) -> Result<LoxObject, LoxError> {
+        let value = self.evaluate(ast, value)?;
+        match ast.local(id) {
+            Some(local) => self.scope(name)?.borrow_mut().assign_at(local, name, value),
+            None => self.globals.assign(name, value)
+        }
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<LoxObject, LoxError> {
+        let left = self.evaluate(ast, left)?;
+        let right = self.evaluate(ast, right)?;
         
         match operator.r#type {
-            TokenType::BangEqual => Ok(LoxObject::Bool(!self.is_equal(&left, &right))),
-            TokenType::EqualEqual => Ok(LoxObject::Bool(self.is_equal(&left, &right))),
-            TokenType::Greater => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Bool(l > r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            },
-            TokenType::GreaterEqual => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Bool(l >= r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            },
-            TokenType::Less => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Bool(l < r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            },
-            TokenType::LessEqual => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Bool(l <= r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            },
-            TokenType::Minus => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Number(l - r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            }
+            TokenType::BangEqual => Ok(LoxObject::Bool(!is_equal(&left, &right))),
+            TokenType::EqualEqual => Ok(LoxObject::Bool(is_equal(&left, &right))),
+            TokenType::Greater => self.comparison(&left, &right, operator, Ordering::is_gt),
+            TokenType::GreaterEqual => self.comparison(&left, &right, operator, Ordering::is_ge),
+            TokenType::Less => self.comparison(&left, &right, operator, Ordering::is_lt),
+            TokenType::LessEqual => self.comparison(&left, &right, operator, Ordering::is_le),
+            TokenType::Minus => self.arithmetic(&left, &right, operator, Operator::Subtract),
             TokenType::Plus => {
-                match (left.clone(), right.clone()) {
-                    (LoxObject::Number(l), LoxObject::Number(r)) => Ok(LoxObject::Number(l + r)),
-                    (LoxObject::String(l), LoxObject::String(r)) => Ok(LoxObject::String(l + &r)),
+                match (&left, &right) {
+                    (LoxObject::String(l), LoxObject::String(r)) => Ok(LoxObject::from([&l[..], &r[..]].concat())),
+                    _ if left.as_number().is_some() && right.as_number().is_some() => self.arithmetic(&left, &right, operator, Operator::Add),
                     _ => {
                         let msg = format!("{} and {} must both be numbers or both be strings.", left, right);
-                        Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
+                        Err(LoxError::RuntimeError { msg: msg.into(), span: operator.span })
                     }
                 }
             }
-            TokenType::Slash => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Number(l / r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            }
-            TokenType::Star => {
-                if let (LoxObject::Number(l), LoxObject::Number(r)) = (left.clone(), right.clone()) {
-                    Ok(LoxObject::Number(l * r))
-                } else {
-                    let msg = format!("{} and {} must be numbers.", left, right);
-                    Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line })
-                }
-            }
+            TokenType::Slash => self.arithmetic(&left, &right, operator, Operator::Divide),
+            TokenType::Star => self.arithmetic(&left, &right, operator, Operator::Multiply),
+            TokenType::SlashSlash => self.arithmetic(&left, &right, operator, Operator::IntDivide),
+            TokenType::Percent => self.arithmetic(&left, &right, operator, Operator::Remainder),
             _ => {
                 Ok(LoxObject::Nil)
             }
         }
     }
 
-    fn visit_call_expr(&self, callee: Box<Expr>, paren: Token, arguments: Vec<Expr>) -> Result<LoxObject, LoxError> {
-        todo!()
+    fn visit_call_expr(&mut self, ast: &Ast, callee: ExprId, paren: &Token, arguments: &[ExprId]) -> Result<LoxObject, LoxError> {
+        let callee = self.evaluate(ast, callee)?;
+
+        let mut args = Vec::with_capacity(arguments.len());
+        for &argument in arguments {
+            args.push(self.evaluate(ast, argument)?);
+        }
+
+        let function: Rc<dyn LoxCallable> = match callee {
+            LoxObject::Function(function) => function,
+            LoxObject::Native(native) => native,
+            LoxObject::Class(class) => class,
+            _ => return Err(LoxError::RuntimeError { msg: "Can only call functions and classes.".into(), span: paren.span })
+        };
+
+        if args.len() != function.arity() {
+            let msg = format!("Expected {} arguments but got {}.", function.arity(), args.len());
+            return Err(LoxError::RuntimeError { msg: msg.into(), span: paren.span });
+        }
+
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(LoxError::RuntimeError { msg: "Stack overflow.".into(), span: paren.span });
+        }
+
+        self.depth += 1;
+        let caller = std::mem::replace(&mut self.call, paren.span);
+        let result = function.call(self, args, paren);
+        self.call = caller;
+        self.depth -= 1;
+        result
     }
 
-    fn visit_get_expr(&self, object: Box<Expr>, name: Token) -> Result<LoxObject, LoxError> {
-        todo!()
+    fn visit_get_expr(&mut self, ast: &Ast, object: ExprId, name: &Token) -> Result<LoxObject, LoxError> {
+        match self.evaluate(ast, object)? {
+            LoxObject::Instance(instance) => LoxInstance::get(&instance, name),
+            _ => Err(LoxError::RuntimeError { msg: "Only instances have properties.".into(), span: name.span })
+        }
     }
 
     #[inline]
-    fn visit_grouping_expr(&mut self, expression: Box<Expr>) -> Result<LoxObject, LoxError> {
-        self.evaluate(*expression)
+    fn visit_grouping_expr(&mut self, ast: &Ast, expression: ExprId) -> Result<LoxObject, LoxError> {
+        self.evaluate(ast, expression)
     }
 
     #[inline]
-    fn visit_literal_expr(&self, value: LoxLiteral) -> Result<LoxObject, LoxError> {
+    fn visit_literal_expr(&mut self, _ast: &Ast, value: &LoxLiteral) -> Result<LoxObject, LoxError> {
         Ok(match value {
-            LoxLiteral::String(str) => LoxObject::String(str),
-            LoxLiteral::Number(num) => LoxObject::Number(num),
-            LoxLiteral::Bool(bool) => LoxObject::Bool(bool),
+            LoxLiteral::String(str) => LoxObject::String(str.as_value()),
+            LoxLiteral::Int(int) => LoxObject::Int(*int),
+            LoxLiteral::Number(num) => LoxObject::Number(*num),
+            LoxLiteral::Bool(bool) => LoxObject::Bool(*bool),
             LoxLiteral::Nil => LoxObject::Nil
         })
     }
 
-    fn visit_logical_expr(&mut self, left: Box<Expr>, operator: Token, right: Box<Expr>) -> Result<LoxObject, LoxError> {
-        let left = self.evaluate(*left)?;
+    fn visit_logical_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<LoxObject, LoxError> {
+        let left = self.evaluate(ast, left)?;
 
         if operator.r#type == TokenType::Or {
-            if self.is_truthy(&left) {
+            if is_truthy(&left) {
                 return Ok(left);
             }
         } else {
-            if !self.is_truthy(&left) {
+            if !is_truthy(&left) {
                 return Ok(left);
             }
         }
 
-        self.evaluate(*right)
+        self.evaluate(ast, right)
     }
 
-    fn visit_set_expr(&self, object: Box<Expr>, name: Token, value: Box<Expr>) -> Result<LoxObject, LoxError> {
-        todo!()
+    fn visit_set_expr(&mut self, ast: &Ast, object: ExprId, name: &Token, value: ExprId) -> Result<LoxObject, LoxError> {
+        let instance = match self.evaluate(ast, object)? {
+            LoxObject::Instance(instance) => instance,
+            _ => return Err(LoxError::RuntimeError { msg: "Only instances have fields.".into(), span: name.span })
+        };
+
+        let value = self.evaluate(ast, value)?;
+        instance.borrow_mut().set(name, value.clone());
+        Ok(value)
     }
 
-    fn visit_super_expr(&self, keyword: Token, method: Token) -> Result<LoxObject, LoxError> {
-        todo!()
+    fn visit_super_expr(&mut self, ast: &Ast, id: ExprId, keyword: &Token, method: &Token) -> Result<LoxObject, LoxError> {
+        // The resolver guarantees all of this for parsed code, but a program
+        // loaded from a cache file hasn't been through the parser.
+        let unbound = |msg: &str| LoxError::RuntimeError { msg: msg.into(), span: keyword.span };
+
+        // `this` is the only binding in the scope just inside the one that
+        // binds `super`.
+        let (local, this_depth) = match ast.local(id) {
+            Some(local) if local.depth > 0 => (local, local.depth - 1),
+            _ => return Err(unbound("'super' is not bound to a class."))
+        };
+        let this = Token { lexeme: Some(Symbol::THIS), ..*keyword };
+        let environment = self.scope(keyword)?.borrow();
+
+        let superclass = match environment.get_at(local, keyword)? {
+            LoxObject::Class(class) => class,
+            _ => return Err(unbound("'super' is not bound to a class."))
+        };
+        let instance = match environment.get_at(Local { depth: this_depth, slot: 0 }, &this)? {
+            LoxObject::Instance(instance) => instance,
+            _ => return Err(unbound("'this' is not bound to an instance."))
+        };
+
+        match superclass.find_method(method.name()) {
+            Some(function) => Ok(LoxObject::Function(Rc::new(function.bind(instance)))),
+            None => {
+                let msg = format!("Undefined property '{}'.", method.name());
+                Err(LoxError::RuntimeError { msg: msg.into(), span: method.span })
+            }
+        }
     }
 
-    fn visit_this_expr(&self, keyword: Token) -> Result<LoxObject, LoxError> {
-        todo!()
+    #[inline]
+    fn visit_this_expr(&mut self, ast: &Ast, id: ExprId, keyword: &Token) -> Result<LoxObject, LoxError> {
+        self.look_up_variable(ast, id, keyword)
     }
 
-    fn visit_unary_expr(&mut self, operator: Token, right: Box<Expr>) -> Result<LoxObject, LoxError> {
-        let right = self.evaluate(*right)?;
+    fn visit_unary_expr(&mut self, ast: &Ast, operator: &Token, right: ExprId) -> Result<LoxObject, LoxError> {
+        let right = self.evaluate(ast, right)?;
         
         Ok(match operator.r#type {
-            TokenType::Bang => LoxObject::Bool(!self.is_truthy(&right)),
+            TokenType::Bang => LoxObject::Bool(!is_truthy(&right)),
+            // A `${}` in a string literal.
+            TokenType::Interpolation => LoxObject::from(stringify(&right)),
             TokenType::Minus => {
-                if let LoxObject::Number(num) = right {
-                    LoxObject::Number(-num)
+                if let Some(num) = right.as_number() {
+                    num.negate()
+                        .map(LoxObject::from)
+                        .map_err(|msg| LoxError::RuntimeError { msg: msg.into(), span: operator.span })?
                 } else {
                     let msg = format!("{} must be a number.", right);
-                    return Err(LoxError::RuntimeError { msg: msg.into(), line: operator.line });
+                    return Err(LoxError::RuntimeError { msg: msg.into(), span: operator.span });
                 }
             }
             _ => LoxObject::Nil
@@ -224,81 +365,130 @@ impl VisitorExpr<LoxObject> for Interpreter {
     }
 
     #[inline]
-    fn visit_variable_expr(&mut self, name: Token) -> Result<LoxObject, LoxError> {
-        self.environment.borrow().get(name)
+    fn visit_variable_expr(&mut self, ast: &Ast, id: ExprId, name: &Token) -> Result<LoxObject, LoxError> {
+        self.look_up_variable(ast, id, name)
     }
 }
 
-impl VisitorStmt<()> for Interpreter {
+impl VisitorStmt<Completion> for Interpreter {
+    #[inline]
+    fn execute(&mut self, ast: &Ast, stmt: StmtId) -> Result<Completion, LoxError> {
+        self.nested(|interpreter| walk_stmt(interpreter, ast, stmt))
+    }
+
     #[inline]
-    fn visit_block_stmt(&mut self, statements: Vec<Stmt>) -> Result<(), LoxError> {
-        self.execute_block(statements, self.environment.clone())
+    fn visit_block_stmt(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<Completion, LoxError> {
+        let environment = Environment::from(self.environment.clone());
+        self.execute_block(ast, statements, Rc::new(RefCell::new(environment)))
     }
 
-    fn visit_class_stmt(&self, name: Token, superclass: Option<Expr>, methods: Vec<Stmt>) -> Result<(), LoxError> {
-        todo!()
+    fn visit_class_stmt(&mut self, ast: &Ast, name: &Token, superclass: Option<ExprId>, methods: &[StmtId]) -> Result<Completion, LoxError> {
+        let superclass = match superclass {
+            Some(expr) => {
+                let span = match ast.expr(expr) {
+                    Expr::Variable { name } => ast.token(*name).span,
+                    _ => name.span
+                };
+                match self.evaluate(ast, expr)? {
+                    LoxObject::Class(class) => Some(class),
+                    _ => return Err(LoxError::RuntimeError { msg: "Superclass must be a class.".into(), span })
+                }
+            }
+            None => None
+        };
+
+        let enclosing = self.environment.clone();
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::from(enclosing.clone());
+            environment.define(LoxObject::Class(superclass.clone()));
+            self.environment = Some(Rc::new(RefCell::new(environment)));
+        }
+
+        let mut class_methods = HashMap::new();
+        for &method in methods {
+            if let Stmt::Function { name, .. } = ast.stmt(method) {
+                let method_name = ast.token(*name).name();
+                let is_initializer = method_name == Symbol::INIT;
+                let function = LoxFunction::new(self.ast.clone(), method, self.environment.clone(), is_initializer);
+                class_methods.insert(method_name, Rc::new(function));
+            }
+        }
+
+        self.environment = enclosing;
+
+        // Methods only see the class through their closures once it is called,
+        // so it can be defined after them.
+        let class = LoxClass::new(name.name().as_rc(), superclass, class_methods);
+        self.define(name, LoxObject::Class(Rc::new(class)));
+        Ok(Completion::Normal)
     }
 
     #[inline]
-    fn visit_expression_stmt(&mut self, expression: Expr) -> Result<(), LoxError> {
-        match self.evaluate(expression) {
-            Ok(_) => Ok(()),
+    fn visit_expression_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<Completion, LoxError> {
+        match self.evaluate(ast, expression) {
+            Ok(_) => Ok(Completion::Normal),
             Err(e) => Err(e)
         }
     }
 
-    fn visit_function_stmt(&self, name: Token, params: Vec<Token>, body: Vec<Stmt>) -> Result<(), LoxError> {
-        todo!()
+    fn visit_function_stmt(&mut self, _ast: &Ast, id: StmtId, name: &Token, _params: &[TokenId], _body: &[StmtId]) -> Result<Completion, LoxError> {
+        let function = LoxFunction::new(self.ast.clone(), id, self.environment.clone(), false);
+        self.define(name, LoxObject::Function(Rc::new(function)));
+        Ok(Completion::Normal)
     }
 
-    fn visit_if_stmt(&mut self, condition: Expr, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>) -> Result<(), LoxError> {
-        let v = self.evaluate(condition)?;
-        if self.is_truthy(&v) {
-            self.execute(*then_branch)?;
+    fn visit_if_stmt(&mut self, ast: &Ast, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Result<Completion, LoxError> {
+        let v = self.evaluate(ast, condition)?;
+        if is_truthy(&v) {
+            self.execute(ast, then_branch)
         } else if let Some(else_branch) = else_branch {
-            self.execute(*else_branch)?;
+            self.execute(ast, else_branch)
+        } else {
+            Ok(Completion::Normal)
         }
-        Ok(())
     }
 
     #[inline]
-    fn visit_print_stmt(&mut self, expression: Expr) -> Result<(), LoxError> {
-        match self.evaluate(expression) {
+    fn visit_print_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<Completion, LoxError> {
+        match self.evaluate(ast, expression) {
             Ok(expr) => {
-                println!("{}", self.stringify(expr));
-                Ok(())
+                println!("{}", stringify(&expr));
+                Ok(Completion::Normal)
             }
             Err(e) => Err(e)
         }
     }
 
-    fn visit_return_stmt(&self, keyword: Token, value: Option<Expr>) -> Result<(), LoxError> {
-        todo!()
+    fn visit_return_stmt(&mut self, ast: &Ast, _keyword: &Token, value: Option<ExprId>) -> Result<Completion, LoxError> {
+        let value = match value {
+            Some(expr) => self.evaluate(ast, expr)?,
+            None => LoxObject::Nil
+        };
+        Ok(Completion::Return(value))
     }
 
-    fn visit_var_stmt(&mut self, name: Token, initializer: Option<Expr>) -> Result<(), LoxError> {
-        if let Some(expr) = initializer {
-            match self.evaluate(expr) {
-                Ok(v) => {
-                    self.environment.borrow_mut().define(name.lexeme.unwrap(), v);
-                }
-                Err(e) => return Err(e)
-            };
-        }
-        Ok(())
+    fn visit_var_stmt(&mut self, ast: &Ast, name: &Token, initializer: Option<ExprId>) -> Result<Completion, LoxError> {
+        let value = match initializer {
+            Some(expr) => self.evaluate(ast, expr)?,
+            None => LoxObject::Nil
+        };
+        self.define(name, value);
+        Ok(Completion::Normal)
     }
 
-    fn visit_while_stmt(&mut self, condition: Expr, body: Box<Stmt>) -> Result<(), LoxError> {
+    fn visit_while_stmt(&mut self, ast: &Ast, condition: ExprId, body: StmtId) -> Result<Completion, LoxError> {
         loop {
-            let bool = self.evaluate(condition.clone())?;
+            let bool = self.evaluate(ast, condition)?;
 
-            if self.is_truthy(&bool) {
-                self.execute(*body.clone())?;
+            if is_truthy(&bool) {
+                if let Completion::Return(value) = self.execute(ast, body)? {
+                    return Ok(Completion::Return(value));
+                }
             } else {
                 break
             }
         }
 
-        Ok(())
+        Ok(Completion::Normal)
     }
 }
\ No newline at end of file
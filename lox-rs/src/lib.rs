@@ -1,8 +1,23 @@
-mod token;
-mod error;
-mod ast;
+mod intern;
 mod environment;
+mod callable;
+mod class;
+mod value;
+mod number;
+mod primitive;
 
+pub mod token;
+pub mod ast;
+pub mod error;
 pub mod scanner;
 pub mod parser;
-pub mod interpreter;
\ No newline at end of file
+pub mod resolver;
+pub mod optimizer;
+pub mod interpreter;
+pub mod compiler;
+pub mod vm;
+pub mod printer;
+pub mod cache;
+pub mod gc;
+
+pub use ast::LoxObject;
\ No newline at end of file
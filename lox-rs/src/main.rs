@@ -1,80 +1,287 @@
-use std::{env, process::exit, fs::File, io::{Read, self, Write}};
+use std::{env, panic, process::exit, fs::{self, File}, io::{Read, self, Write}, path::Path, thread};
 
-use lox_rs::{scanner::Scanner, parser::Parser, interpreter::Interpreter};
+use lox_rs::{
+    scanner::Scanner, parser::Parser, resolver::Resolver, optimizer::Optimizer, interpreter::{self, Interpreter},
+    compiler::Compiler, vm::Vm, printer::AstPrinter, error::LoxError, ast::Ast, cache,
+};
+
+struct Options {
+    // Print diagnostics as one `[line N]` line each instead of quoting the source.
+    plain: bool,
+    // Compile to bytecode and run on the VM instead of walking the AST.
+    vm: bool,
+    // Print the token stream and stop before parsing.
+    tokens: bool,
+    // Print the parsed program and stop before running it.
+    ast: bool,
+    // Fold constant expressions and drop dead branches before running.
+    optimize: bool
+}
+
+enum Backend {
+    Interpreter(Interpreter),
+    Vm(Vm)
+}
+
+impl Backend {
+    fn new(options: &Options) -> Backend {
+        if options.vm {
+            Backend::Vm(Vm::new())
+        } else {
+            Backend::Interpreter(Interpreter::new())
+        }
+    }
+}
+
+const USAGE: &str = "Usage: lox [--plain] [--vm] [--tokens] [--ast] [-O] [script]\n       lox compile <script> [-o <output>]";
 
 fn main() -> io::Result<()> {
-    let args = env::args().collect::<Vec<_>>();
+    // The tree-walker recurses on the native stack, so run everything on a
+    // thread with the stack its nesting limit was sized for.
+    thread::Builder::new()
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(lox)?
+        .join()
+        .unwrap_or_else(|e| panic::resume_unwind(e))
+}
 
-    if args.len() > 2 {
-        println!("Usage: lox [script]");
-        exit(64);
-    } else if args.len() == 2 {
-        run_file(&args[1])?;
-    } else {
-        run_prompt()?;
+fn lox() -> io::Result<()> {
+    let mut options = Options { plain: false, vm: false, tokens: false, ast: false, optimize: false };
+    let mut paths = Vec::new();
+    let mut output = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--plain" => options.plain = true,
+            "--vm" => options.vm = true,
+            "--tokens" => options.tokens = true,
+            "--ast" => options.ast = true,
+            "-O" => options.optimize = true,
+            "-o" => output = args.next(),
+            _ => paths.push(arg)
+        }
+    }
+
+    match paths.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["compile", input] => compile_file(input, output.as_deref(), &options)?,
+        [path] if output.is_none() && *path != "compile" => run_file(path, &options)?,
+        [] if output.is_none() => run_prompt(&options)?,
+        _ => {
+            println!("{}", USAGE);
+            exit(64);
+        }
     }
 
     Ok(())
 }
 
-fn run(source: Vec<u8>) {
-    let mut scanner = Scanner::new(source);
+fn report(errors: &[LoxError], source: &[u8], options: &Options) {
+    for e in errors {
+        if options.plain {
+            println!("{}", e);
+        } else {
+            println!("{}", e.render(source));
+        }
+    }
+}
+
+/// Scans and parses `source`, handling the `--tokens` and `--ast` dump modes.
+/// Returns `None` when there is nothing left to run.
+fn parse(source: &[u8], repl: bool, options: &Options) -> Option<Ast> {
+    let mut scanner = Scanner::new(source.to_vec());
     let tokens = match scanner.scan_tokens() {
         Ok(tokens) => tokens,
         Err(errors) => {
-            errors.iter().for_each(|e| println!("{}", e));
-            return;
+            report(&errors, source, options);
+            return None;
         }
     };
-    // tokens.iter().for_each(|token| println!("{:?}", token));
-    
-    let mut parser = Parser::new(tokens);
+
+    if options.tokens {
+        for token in &tokens {
+            if let Some(doc) = token.doc {
+                for line in doc.as_rc().lines() {
+                    println!("{:<4} /// {}", "", line);
+                }
+            }
+            let lexeme = String::from_utf8_lossy(&source[token.span.start..token.span.end]);
+            println!("{:<4} {:<14} {:<16} {}", token.span.line, format!("{:?}", token.r#type), lexeme, token.literal);
+        }
+        return None;
+    }
+
+    let mut parser = if repl { Parser::new_repl(tokens) } else { Parser::new(tokens) };
     let ast = match parser.parse() {
         Ok(ast) => ast,
-        Err(e) => {
-            println!("{}", e);
-            return;
+        Err(errors) => {
+            report(&errors, source, options);
+            return None;
         }
     };
-    // println!("{:#?}", ast);
 
-    let mut interpreter = Interpreter::new();
-    match interpreter.interpret(ast) {
-        Ok(_) => (),
-        Err(e) => {
-            println!("{}", e);
+    if options.ast {
+        print_ast(ast, source, options);
+        return None;
+    }
+
+    Some(ast)
+}
+
+fn print_ast(ast: Ast, source: &[u8], options: &Options) {
+    let ast = optimize(ast, options);
+    match AstPrinter::new().print(&ast) {
+        Ok(printed) => println!("{}", printed),
+        Err(e) => report(&[e], source, options)
+    }
+}
+
+fn execute(backend: &mut Backend, ast: Ast, source: &[u8], options: &Options) {
+    let ast = match Resolver::new().resolve(ast) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            report(&errors, source, options);
             return;
         }
+    };
+
+    let ast = optimize(ast, options);
+
+    let result = match backend {
+        Backend::Interpreter(interpreter) => interpreter.interpret(ast),
+        Backend::Vm(vm) => Compiler::new().compile(&ast).and_then(|function| vm.interpret(function))
+    };
+
+    if let Err(e) = result {
+        report(&[e], source, options);
     }
 }
 
-fn run_file(path: &str) -> io::Result<()> {
-    let mut source = String::new();
+fn optimize(ast: Ast, options: &Options) -> Ast {
+    if options.optimize {
+        Optimizer::new().optimize(ast)
+    } else {
+        ast
+    }
+}
+
+fn run(backend: &mut Backend, source: Vec<u8>, repl: bool, options: &Options) {
+    if let Some(ast) = parse(&source, repl, options) {
+        execute(backend, ast, &source, options);
+    }
+}
+
+fn run_file(path: &str, options: &Options) -> io::Result<()> {
+    let mut source = Vec::new();
 
     let mut file = File::open(path)?;
 
-    file.read_to_string(&mut source)?;
+    file.read_to_end(&mut source)?;
 
-    let source = source.into_bytes();
+    // A compiled program skips scanning and parsing. Its source is not
+    // available, so diagnostics use the plain `[line N]` format.
+    if cache::is_cache(&source) {
+        let ast = match cache::load(&source) {
+            Ok(ast) => ast,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(());
+            }
+        };
 
-    run(source);
+        if options.ast {
+            print_ast(ast, &[], options);
+        } else {
+            execute(&mut Backend::new(options), ast, &[], options);
+        }
+        return Ok(());
+    }
+
+    run(&mut Backend::new(options), source, false, options);
 
     Ok(())
 }
 
-fn run_prompt() -> io::Result<()> {
+/// `lox compile foo.lox -o foo.loxc`: parses the script once and writes the
+/// result so later runs can load it directly. The output defaults to the
+/// input path with a `.loxc` extension.
+fn compile_file(input: &str, output: Option<&str>, options: &Options) -> io::Result<()> {
+    let source = fs::read(input)?;
+
+    let ast = match parse(&source, false, options) {
+        Some(ast) => ast,
+        None => return Ok(())
+    };
+
+    let output = match output {
+        Some(output) => output.to_owned(),
+        None => Path::new(input).with_extension("loxc").to_string_lossy().into_owned()
+    };
+
+    fs::write(output, cache::save(&ast))
+}
+
+fn run_prompt(options: &Options) -> io::Result<()> {
+    let mut backend = Backend::new(options);
+
     loop {
         let mut input = String::new();
 
         print!("> ");
         io::stdout().flush()?;
 
-        if let Err(e) = io::stdin().read_line(&mut input) {
-            break Err(e)
+        loop {
+            if io::stdin().read_line(&mut input)? == 0 {
+                return Ok(());
+            }
+            if is_balanced(&input) {
+                break;
+            }
+
+            print!(". ");
+            io::stdout().flush()?;
         }
     
         let source = input.into_bytes();
 
-        run(source);
+        run(&mut backend, source, true, options);
+    }
+}
+
+/// Whether every `(` and `{` in `input` has been closed, ignoring string
+/// contents and comments, so the REPL knows to keep reading lines.
+fn is_balanced(input: &str) -> bool {
+    let mut depth = 0i32;
+    let mut in_string = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            }
+            _ if in_string => (),
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut nesting = 1;
+                while nesting > 0 {
+                    match chars.next() {
+                        Some('/') if chars.next_if_eq(&'*').is_some() => nesting += 1,
+                        Some('*') if chars.next_if_eq(&'/').is_some() => nesting -= 1,
+                        Some(_) => (),
+                        None => return false
+                    }
+                }
+            }
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            _ => ()
+        }
     }
-}
\ No newline at end of file
+
+    !in_string && depth <= 0
+}
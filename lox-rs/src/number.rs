@@ -0,0 +1,181 @@
+//! Arithmetic on Lox's two numeric types, shared by the interpreter, the VM
+//! and the optimizer so that all three mix them the same way.
+//!
+//! Integer operands give an integer result, except for `/`, which always
+//! divides as floats so that `7 / 2` is still `3.5`. An integer meeting a
+//! float is converted to a float first. Integer arithmetic is checked: an
+//! overflow, or an integer divided by zero, is a runtime error, while float
+//! arithmetic follows IEEE 754 as it always has. `//` and `%` truncate
+//! towards zero, so `a == (a // b) * b + a % b`.
+
+use std::cmp::Ordering;
+
+use crate::token::TokenType;
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64)
+}
+
+/// The binary operators that take two numbers and produce one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    IntDivide,
+    Remainder
+}
+
+impl Operator {
+    pub(crate) fn from_token(r#type: TokenType) -> Option<Operator> {
+        Some(match r#type {
+            TokenType::Plus => Operator::Add,
+            TokenType::Minus => Operator::Subtract,
+            TokenType::Star => Operator::Multiply,
+            TokenType::Slash => Operator::Divide,
+            TokenType::SlashSlash => Operator::IntDivide,
+            TokenType::Percent => Operator::Remainder,
+            _ => return None
+        })
+    }
+}
+
+const OVERFLOW: &str = "Integer overflow.";
+const DIVISION_BY_ZERO: &str = "Division by zero.";
+
+impl Number {
+    #[inline]
+    fn as_f64(self) -> f64 {
+        match self {
+            Number::Int(int) => int as f64,
+            Number::Float(float) => float
+        }
+    }
+
+    /// Applies `operator`, or returns the message of the runtime error it
+    /// raises.
+    pub(crate) fn arithmetic(self, operator: Operator, other: Number) -> Result<Number, &'static str> {
+        let (l, r) = match (self, other) {
+            (Number::Int(l), Number::Int(r)) => return int_arithmetic(operator, l, r),
+            (l, r) => (l.as_f64(), r.as_f64())
+        };
+
+        Ok(Number::Float(match operator {
+            Operator::Add => l + r,
+            Operator::Subtract => l - r,
+            Operator::Multiply => l * r,
+            Operator::Divide => l / r,
+            Operator::IntDivide => (l / r).trunc(),
+            Operator::Remainder => l % r
+        }))
+    }
+
+    pub(crate) fn negate(self) -> Result<Number, &'static str> {
+        match self {
+            Number::Int(int) => int.checked_neg().map(Number::Int).ok_or(OVERFLOW),
+            Number::Float(float) => Ok(Number::Float(-float))
+        }
+    }
+
+    /// Mixed operands are compared as floats. `None` if either is NaN.
+    pub(crate) fn compare(self, other: Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(l), Number::Int(r)) => Some(l.cmp(&r)),
+            (l, r) => l.as_f64().partial_cmp(&r.as_f64())
+        }
+    }
+
+    /// `1 == 1.0`, like every other comparison between the two types.
+    #[inline]
+    pub(crate) fn equals(self, other: Number) -> bool {
+        self.compare(other) == Some(Ordering::Equal)
+    }
+}
+
+fn int_arithmetic(operator: Operator, l: i64, r: i64) -> Result<Number, &'static str> {
+    if r == 0 && matches!(operator, Operator::Divide | Operator::IntDivide | Operator::Remainder) {
+        return Err(DIVISION_BY_ZERO);
+    }
+
+    let result = match operator {
+        Operator::Add => l.checked_add(r),
+        Operator::Subtract => l.checked_sub(r),
+        Operator::Multiply => l.checked_mul(r),
+        Operator::Divide => return Ok(Number::Float(l as f64 / r as f64)),
+        Operator::IntDivide => l.checked_div(r),
+        // Only `i64::MIN % -1` overflows, and its remainder is 0.
+        Operator::Remainder => Some(l.wrapping_rem(r))
+    };
+    result.map(Number::Int).ok_or(OVERFLOW)
+}
+
+/// Formats a float so that it never reads as an integer: `1.0`, not `1`.
+pub(crate) fn format_float(float: f64) -> String {
+    if float.is_finite() && float.fract() == 0.0 {
+        format!("{:.1}", float)
+    } else {
+        float.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(l: i64, operator: Operator, r: i64) -> Result<Number, &'static str> {
+        Number::Int(l).arithmetic(operator, Number::Int(r))
+    }
+
+    fn assert_int(result: Result<Number, &'static str>, expected: i64) {
+        assert!(matches!(result, Ok(Number::Int(n)) if n == expected), "{:?}", result);
+    }
+
+    fn assert_float(result: Result<Number, &'static str>, expected: f64) {
+        assert!(matches!(result, Ok(Number::Float(n)) if n == expected), "{:?}", result);
+    }
+
+    #[test]
+    fn integer_overflow() {
+        assert_eq!(int(i64::MAX, Operator::Add, 1).unwrap_err(), OVERFLOW);
+        assert_eq!(int(i64::MIN, Operator::Subtract, 1).unwrap_err(), OVERFLOW);
+        assert_eq!(int(i64::MAX, Operator::Multiply, 2).unwrap_err(), OVERFLOW);
+        assert_eq!(int(i64::MIN, Operator::IntDivide, -1).unwrap_err(), OVERFLOW);
+        assert_eq!(Number::Int(i64::MIN).negate().unwrap_err(), OVERFLOW);
+        assert_int(int(i64::MIN, Operator::Remainder, -1), 0);
+    }
+
+    #[test]
+    fn integer_division_truncates_towards_zero() {
+        assert_int(int(7, Operator::IntDivide, 2), 3);
+        assert_int(int(-7, Operator::IntDivide, 2), -3);
+        assert_int(int(-7, Operator::Remainder, 2), -1);
+        assert_int(int(7, Operator::Remainder, -2), 1);
+        assert_float(int(7, Operator::Divide, 2), 3.5);
+    }
+
+    #[test]
+    fn integer_division_by_zero() {
+        for operator in [Operator::Divide, Operator::IntDivide, Operator::Remainder] {
+            assert_eq!(int(1, operator, 0).unwrap_err(), DIVISION_BY_ZERO, "{:?}", operator);
+        }
+    }
+
+    #[test]
+    fn float_division_by_zero_follows_ieee_754() {
+        assert_float(Number::Float(1.0).arithmetic(Operator::Divide, Number::Int(0)), f64::INFINITY);
+        assert!(matches!(Number::Int(1).arithmetic(Operator::Remainder, Number::Float(0.0)), Ok(Number::Float(n)) if n.is_nan()));
+    }
+
+    #[test]
+    fn mixed_operands_are_promoted_to_float() {
+        assert_float(Number::Int(1).arithmetic(Operator::Add, Number::Float(0.5)), 1.5);
+        assert_float(Number::Float(2.0).arithmetic(Operator::Multiply, Number::Int(3)), 6.0);
+        assert_float(Number::Float(7.5).arithmetic(Operator::IntDivide, Number::Int(2)), 3.0);
+        assert!(Number::Int(1).equals(Number::Float(1.0)));
+        assert_eq!(Number::Int(2).compare(Number::Float(1.5)), Some(Ordering::Greater));
+        assert_eq!(Number::Int(1).compare(Number::Float(f64::NAN)), None);
+    }
+}
@@ -0,0 +1,206 @@
+use std::cmp::Ordering;
+
+use crate::{
+    ast::{Ast, Expr, ExprId, LoxLiteral, Stmt, StmtId},
+    intern::Symbol,
+    number::Operator,
+    primitive::{is_equal, is_truthy, stringify},
+    token::TokenType,
+};
+
+/// Optional pass run after the resolver, enabled with `-O`. It folds
+/// operators whose operands are all literals and removes `if` and `while`
+/// branches whose condition is a literal that never selects them.
+///
+/// Nodes are rewritten in place, so ids, and the resolver's side table, stay
+/// valid. An operation the interpreter would reject, such as `1 + "a"`, is
+/// left alone so the error is still raised when, and if, it runs. That
+/// includes integer overflow and division by zero.
+pub struct Optimizer;
+
+impl Default for Optimizer {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Optimizer {
+    #[inline]
+    pub fn new() -> Optimizer {
+        Optimizer
+    }
+
+    pub fn optimize(&mut self, mut ast: Ast) -> Ast {
+        let mut statements = std::mem::take(ast.statements_mut());
+        self.block(&mut ast, &mut statements);
+        *ast.statements_mut() = statements;
+        ast
+    }
+
+    /// Optimizes each statement in `statements` and drops the ones left with
+    /// nothing to do.
+    fn block(&mut self, ast: &mut Ast, statements: &mut Vec<StmtId>) {
+        statements.retain(|&stmt| self.statement(ast, stmt));
+    }
+
+    /// Returns `false` if the statement was reduced to a no-op.
+    fn statement(&mut self, ast: &mut Ast, id: StmtId) -> bool {
+        match ast.stmt(id).clone() {
+            Stmt::Block { mut statements } => {
+                self.block(ast, &mut statements);
+                let live = !statements.is_empty();
+                *ast.stmt_mut(id) = Stmt::Block { statements };
+                live
+            }
+            Stmt::Class { superclass, methods, .. } => {
+                if let Some(superclass) = superclass {
+                    self.expression(ast, superclass);
+                }
+                for method in methods {
+                    self.statement(ast, method);
+                }
+                true
+            }
+            Stmt::Function { name, params, mut body } => {
+                self.block(ast, &mut body);
+                *ast.stmt_mut(id) = Stmt::Function { name, params, body };
+                true
+            }
+            Stmt::If { condition, then_branch, else_branch } => {
+                let condition = self.expression(ast, condition);
+                let then_live = self.statement(ast, then_branch);
+                let else_live = else_branch.map(|branch| self.statement(ast, branch));
+
+                let taken = match condition {
+                    Some(value) if is_truthy(&value) => (then_branch, then_live),
+                    Some(_) => match (else_branch, else_live) {
+                        (Some(branch), Some(live)) => (branch, live),
+                        _ => return self.remove(ast, id)
+                    },
+                    None => return true
+                };
+
+                let (branch, live) = taken;
+                *ast.stmt_mut(id) = ast.stmt(branch).clone();
+                live
+            }
+            Stmt::While { condition, body } => {
+                let condition = self.expression(ast, condition);
+                self.statement(ast, body);
+
+                match condition {
+                    Some(value) if !is_truthy(&value) => self.remove(ast, id),
+                    _ => true
+                }
+            }
+            Stmt::Expression { expression } | Stmt::Print { expression } => {
+                self.expression(ast, expression);
+                true
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(ast, value);
+                }
+                true
+            }
+            Stmt::Var { initializer, .. } => {
+                if let Some(initializer) = initializer {
+                    self.expression(ast, initializer);
+                }
+                true
+            }
+        }
+    }
+
+    /// Turns a statement into an empty block, for when it is not in a list it
+    /// can simply be dropped from.
+    fn remove(&mut self, ast: &mut Ast, id: StmtId) -> bool {
+        *ast.stmt_mut(id) = Stmt::Block { statements: Vec::new() };
+        false
+    }
+
+    /// Folds the expression as far as possible and returns its value if it
+    /// is now a literal.
+    fn expression(&mut self, ast: &mut Ast, id: ExprId) -> Option<LoxLiteral> {
+        let value = match ast.expr(id).clone() {
+            Expr::Literal { value } => return Some(value),
+            Expr::Binary { left, operator, right } => {
+                let left = self.expression(ast, left);
+                let right = self.expression(ast, right);
+                binary(ast.token(operator).r#type, &left?, &right?)?
+            }
+            Expr::Unary { operator, right } => {
+                let right = self.expression(ast, right)?;
+                match (ast.token(operator).r#type, right) {
+                    (TokenType::Bang, right) => LoxLiteral::Bool(!is_truthy(&right)),
+                    (TokenType::Minus, right) => LoxLiteral::from(right.as_number()?.negate().ok()?),
+                    (TokenType::Interpolation, right) => LoxLiteral::String(Symbol::intern(&stringify(&right))),
+                    _ => return None
+                }
+            }
+            Expr::Grouping { expression } => self.expression(ast, expression)?,
+            // Only folded when the result is one of the literals, since any
+            // other node would lose its entry in the resolver's side table.
+            Expr::Logical { left, operator, right } => {
+                let left = self.expression(ast, left);
+                let right = self.expression(ast, right);
+                let short_circuits = match ast.token(operator).r#type {
+                    TokenType::Or => is_truthy(left.as_ref()?),
+                    _ => !is_truthy(left.as_ref()?)
+                };
+                if short_circuits { left? } else { right? }
+            }
+            Expr::Assign { value, .. } => {
+                self.expression(ast, value);
+                return None;
+            }
+            Expr::Call { callee, arguments, .. } => {
+                self.expression(ast, callee);
+                for argument in arguments {
+                    self.expression(ast, argument);
+                }
+                return None;
+            }
+            Expr::Get { object, .. } => {
+                self.expression(ast, object);
+                return None;
+            }
+            Expr::Set { object, value, .. } => {
+                self.expression(ast, object);
+                self.expression(ast, value);
+                return None;
+            }
+            Expr::Super { .. } | Expr::This { .. } | Expr::Variable { .. } => return None
+        };
+
+        *ast.expr_mut(id) = Expr::Literal { value: value.clone() };
+        Some(value)
+    }
+}
+
+/// Evaluates a binary operator the way `Interpreter::visit_binary_expr` does,
+/// or returns `None` if that would be a runtime error.
+fn binary(operator: TokenType, left: &LoxLiteral, right: &LoxLiteral) -> Option<LoxLiteral> {
+    match operator {
+        TokenType::EqualEqual => return Some(LoxLiteral::Bool(is_equal(left, right))),
+        TokenType::BangEqual => return Some(LoxLiteral::Bool(!is_equal(left, right))),
+        _ => ()
+    }
+
+    if let (LoxLiteral::String(l), LoxLiteral::String(r)) = (left, right) {
+        return match operator {
+            TokenType::Plus => Some(LoxLiteral::String(Symbol::intern(&[&*l.as_rc(), &*r.as_rc()].concat()))),
+            _ => None
+        };
+    }
+
+    let (l, r) = (left.as_number()?, right.as_number()?);
+    Some(match operator {
+        TokenType::Greater => LoxLiteral::Bool(l.compare(r).is_some_and(Ordering::is_gt)),
+        TokenType::GreaterEqual => LoxLiteral::Bool(l.compare(r).is_some_and(Ordering::is_ge)),
+        TokenType::Less => LoxLiteral::Bool(l.compare(r).is_some_and(Ordering::is_lt)),
+        TokenType::LessEqual => LoxLiteral::Bool(l.compare(r).is_some_and(Ordering::is_le)),
+        operator => LoxLiteral::from(l.arithmetic(Operator::from_token(operator)?, r).ok()?)
+    })
+}
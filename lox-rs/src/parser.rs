@@ -1,35 +1,71 @@
 use crate::{
-    ast::{Expr, LoxLiteral, Stmt},
+    ast::{Ast, Expr, ExprId, LoxLiteral, Stmt, StmtId, TokenId},
     error::LoxError,
-    token::{LoxType, Token, TokenType},
+    intern::Symbol,
+    token::{LoxType, Span, Token, TokenType},
 };
 
+// How deeply blocks, statement bodies and expressions may nest. Every pass
+// after the parser recurses over the tree, so this bounds the native stack
+// they need.
+const MAX_NESTING: usize = 1024;
+
 pub struct Parser {
-    tokens: Vec<Token>,
+    ast: Ast,
     current: usize,
+    repl: bool,
+    nesting: usize,
+    // Set once `MAX_NESTING` is exceeded, after which parsing stops.
+    too_deep: bool,
+    // Errors that leave the parser in a known state, so it carries on
+    // without synchronizing.
+    errors: Vec<LoxError>,
 }
 
 impl Parser {
     #[inline]
     pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+        Parser { ast: Ast::new(tokens), current: 0, repl: false, nesting: 0, too_deep: false, errors: Vec::new() }
     }
 
+    /// A parser for interactive input: a top-level expression statement, or
+    /// a trailing expression without a `;`, is turned into a `print` of its
+    /// value.
     #[inline]
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, LoxError> {
-        let mut statements = Vec::new();
+    pub fn new_repl(tokens: Vec<Token>) -> Parser {
+        Parser { repl: true, ..Parser::new(tokens) }
+    }
 
+    pub fn parse(&mut self) -> Result<Ast, Vec<LoxError>> {
         while !self.is_at_end() {
             match self.declaration() {
-                Ok(stmt) => statements.push(stmt),
-                Err(e) => return Err(e)
+                Ok(stmt) => self.ast.push_statement(stmt),
+                Err(e) => {
+                    self.errors.push(e);
+                    // The rest of a program nested too deeply can't be
+                    // parsed sensibly.
+                    if self.too_deep {
+                        break;
+                    }
+                    self.synchronize();
+                }
             }
         }
 
-        Ok(statements)
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors))
+        }
+
+        Ok(std::mem::take(&mut self.ast))
     }
 
-    fn declaration(&mut self) -> Result<Stmt, LoxError> {
+    fn declaration(&mut self) -> Result<StmtId, LoxError> {
+        if self.matches(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+        if self.matches(&[TokenType::Fun]) {
+            return self.function("function");
+        }
         if self.matches(&[TokenType::Var]) {
             return self.var_declaration();
         }
@@ -37,10 +73,63 @@ impl Parser {
         self.statement()
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, LoxError> {
+    fn class_declaration(&mut self) -> Result<StmtId, LoxError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let mut superclass: Option<ExprId> = None;
+        if self.matches(&[TokenType::Less]) {
+            let superclass_name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            superclass = Some(self.ast.push_expr(Expr::Variable { name: superclass_name }));
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let methods = self.class_body()?;
+
+        Ok(self.ast.push_stmt(Stmt::Class { name, superclass, methods }))
+    }
+
+    fn class_body(&mut self) -> Result<Vec<StmtId>, LoxError> {
+        let mut methods = Vec::new();
+
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(methods)
+    }
+
+    fn function(&mut self, kind: &str) -> Result<StmtId, LoxError> {
+        let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
+
+        self.consume(TokenType::LeftParen, &format!("Expect '(' after {} name.", kind))?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() == 255 {
+                    self.error("Can't have more than 255 parameters.", self.peek().span);
+                }
+                params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
+
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(TokenType::LeftBrace, &format!("Expect '{{' before {} body.", kind))?;
+        let body = self.block()?;
+
+        Ok(self.ast.push_stmt(Stmt::Function { name, params, body }))
+    }
+
+    fn var_declaration(&mut self) -> Result<StmtId, LoxError> {
         let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
 
-        let mut initializer: Option<Expr> = None;
+        let mut initializer: Option<ExprId> = None;
 
         if self.matches(&[TokenType::Equal]) {
             initializer = match self.expression() {
@@ -50,108 +139,168 @@ impl Parser {
         }
 
         self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
-        Ok(Stmt::Var { name, initializer })
+        Ok(self.ast.push_stmt(Stmt::Var { name, initializer }))
     }
 
     #[inline]
-    fn expression(&mut self) -> Result<Expr, LoxError> {
-        self.assignment()
+    fn expression(&mut self) -> Result<ExprId, LoxError> {
+        self.nested(Self::assignment)
     }
 
-    fn assignment(&mut self) -> Result<Expr, LoxError> {
+    fn assignment(&mut self) -> Result<ExprId, LoxError> {
         let expr = self.or()?;
 
         if self.matches(&[TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment()?;
+            let value = self.nested(Self::assignment)?;
 
-            match expr {
-                Expr::Variable { name } => return Ok(Expr::Assign { name, value: Box::new(value) }),
-                _ => return Err(LoxError::ParseError { msg: "Invalid assignment target.".into(), line: equals.line })
-            }
+            let target = match *self.ast.expr(expr) {
+                Expr::Variable { name } => Expr::Assign { name, value },
+                Expr::Get { object, name } => Expr::Set { object, name, value },
+                _ => {
+                    self.error("Invalid assignment target.", self.token(equals).span);
+                    return Ok(expr);
+                }
+            };
+            return Ok(self.ast.push_expr(target));
         }
 
         Ok(expr)
     }
 
-    fn or(&mut self) -> Result<Expr, LoxError> {
+    fn or(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.and()?;
 
         while self.matches(&[TokenType::Or]) {
             let operator = self.previous();
             let right = self.and()?;
-            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) }
+            expr = self.ast.push_expr(Expr::Logical { left: expr, operator, right });
         }
         
         Ok(expr)
     }
 
-    fn and(&mut self) -> Result<Expr, LoxError> {
+    fn and(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.equality()?;
 
         while self.matches(&[TokenType::And]) {
             let operator = self.previous();
             let right = self.equality()?;
-            expr = Expr::Logical { left: Box::new(expr), operator, right: Box::new(right) }
+            expr = self.ast.push_expr(Expr::Logical { left: expr, operator, right });
         }
         
         Ok(expr)
     }
 
-    fn statement(&mut self) -> Result<Stmt, LoxError> {
-        if self.matches(&[TokenType::If]) {
+    fn statement(&mut self) -> Result<StmtId, LoxError> {
+        if self.matches(&[TokenType::For]) {
+            self.for_statement()
+        }
+        else if self.matches(&[TokenType::If]) {
             self.if_statement()
         }
         else if self.matches(&[TokenType::Print]) {
             self.print_statement()
         }
+        else if self.matches(&[TokenType::Return]) {
+            self.return_statement()
+        }
         else if self.matches(&[TokenType::While]) {
             self.while_statement()
         }
         else if self.matches(&[TokenType::LeftBrace]) {
             let statements = self.block()?;
-            Ok(Stmt::Block { statements })
+            Ok(self.ast.push_stmt(Stmt::Block { statements }))
         }
         else {
-            self.expression_statement()
+            // Only statements outside any block or body are parsed with no
+            // nesting open.
+            let echo = self.repl && self.nesting == 0;
+            self.expression_statement(echo)
         }
     }
 
-    fn while_statement(&mut self) -> Result<Stmt, LoxError> {
+    /// Desugars `for (init; cond; incr) body` into
+    /// `{ init; while (cond) { body; incr; } }`. The clauses keep their own
+    /// tokens, so errors still point at the original lines.
+    fn for_statement(&mut self) -> Result<StmtId, LoxError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.matches(&[TokenType::Semicolon]) {
+            None
+        } else if self.matches(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement(false)?)
+        };
+
+        let mut condition: Option<ExprId> = None;
+        if !self.check(TokenType::Semicolon) {
+            condition = Some(self.expression()?);
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let mut increment: Option<ExprId> = None;
+        if !self.check(TokenType::RightParen) {
+            increment = Some(self.expression()?);
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.nested(Self::statement)?;
+
+        if let Some(increment) = increment {
+            let increment = self.ast.push_stmt(Stmt::Expression { expression: increment });
+            body = self.ast.push_stmt(Stmt::Block { statements: vec![body, increment] });
+        }
+
+        let condition = match condition {
+            Some(condition) => condition,
+            None => self.ast.push_expr(Expr::Literal { value: LoxLiteral::Bool(true) })
+        };
+        body = self.ast.push_stmt(Stmt::While { condition, body });
+
+        if let Some(initializer) = initializer {
+            body = self.ast.push_stmt(Stmt::Block { statements: vec![initializer, body] });
+        }
+
+        Ok(body)
+    }
+
+    fn while_statement(&mut self) -> Result<StmtId, LoxError> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
 
         let condition = self.expression()?;
 
         self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
 
-        let body = Box::new(self.statement()?);
+        let body = self.nested(Self::statement)?;
 
-        Ok(Stmt::While { condition, body })
+        Ok(self.ast.push_stmt(Stmt::While { condition, body }))
     }
 
-    fn if_statement(&mut self) -> Result<Stmt, LoxError> {
+    fn if_statement(&mut self) -> Result<StmtId, LoxError> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
         let condition = self.expression()?;
         self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
 
-        let then_branch = Box::new(self.statement()?);
+        let then_branch = self.nested(Self::statement)?;
         
-        let mut else_branch: Option<Box<Stmt>> = None;
+        let mut else_branch: Option<StmtId> = None;
         if self.matches(&[TokenType::Else]) {
-            else_branch = match self.statement() {
-                Ok(stmt) => Some(Box::new(stmt)),
+            else_branch = match self.nested(Self::statement) {
+                Ok(stmt) => Some(stmt),
                 Err(e) => return Err(e)
             };
         }
 
-        Ok(Stmt::If { condition, then_branch, else_branch })
+        Ok(self.ast.push_stmt(Stmt::If { condition, then_branch, else_branch }))
     }
 
-    fn block(&mut self) -> Result<Vec<Stmt>, LoxError> {
+    fn block(&mut self) -> Result<Vec<StmtId>, LoxError> {
         let mut statements = Vec::new();
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration()?);
+            statements.push(self.nested(Self::declaration)?);
         }
 
         self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
@@ -159,35 +308,55 @@ impl Parser {
         Ok(statements)
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, LoxError> {
+    fn print_statement(&mut self) -> Result<StmtId, LoxError> {
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
-        Ok(Stmt::Print { expression: expr })
+        Ok(self.ast.push_stmt(Stmt::Print { expression: expr }))
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, LoxError> {
+    fn return_statement(&mut self) -> Result<StmtId, LoxError> {
+        let keyword = self.previous();
+
+        let mut value: Option<ExprId> = None;
+        if !self.check(TokenType::Semicolon) {
+            value = Some(self.expression()?);
+        }
+
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(self.ast.push_stmt(Stmt::Return { keyword, value }))
+    }
+
+    /// With `echo`, the statement prints its value, as a top-level
+    /// expression does at the prompt.
+    fn expression_statement(&mut self, echo: bool) -> Result<StmtId, LoxError> {
         let expr = self.expression()?;
+        if self.repl && self.is_at_end() {
+            return Ok(self.ast.push_stmt(Stmt::Print { expression: expr }));
+        }
         self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
-        Ok(Stmt::Expression { expression: expr })
+        if echo {
+            return Ok(self.ast.push_stmt(Stmt::Print { expression: expr }));
+        }
+        Ok(self.ast.push_stmt(Stmt::Expression { expression: expr }))
     }
 
-    fn equality(&mut self) -> Result<Expr, LoxError> {
+    fn equality(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.comparison()?;
 
         while self.matches(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
             let right = self.comparison()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
+            expr = self.ast.push_expr(Expr::Binary {
+                left: expr,
                 operator,
-                right: Box::new(right)
-            }
+                right
+            })
         }
 
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, LoxError> {
+    fn comparison(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.term()?;
 
         while self.matches(&[
@@ -198,119 +367,210 @@ impl Parser {
         ]) {
             let operator = self.previous();
             let right = self.term()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
+            expr = self.ast.push_expr(Expr::Binary {
+                left: expr,
                 operator,
-                right: Box::new(right)
-            }
+                right
+            })
         }
 
         Ok(expr)
     }
 
-    fn term(&mut self) -> Result<Expr, LoxError> {
+    fn term(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.factor()?;
 
         while self.matches(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
             let right = self.factor()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
+            expr = self.ast.push_expr(Expr::Binary {
+                left: expr,
                 operator,
-                right: Box::new(right)
-            }
+                right
+            })
         }
 
         Ok(expr)
     }
 
-    fn factor(&mut self) -> Result<Expr, LoxError> {
+    fn factor(&mut self) -> Result<ExprId, LoxError> {
         let mut expr = self.unary()?;
 
-        while self.matches(&[TokenType::Slash, TokenType::Star]) {
+        while self.matches(&[TokenType::Slash, TokenType::Star, TokenType::Percent]) || self.divides() {
             let operator = self.previous();
             let right = self.unary()?;
-            expr = Expr::Binary {
-                left: Box::new(expr),
+            expr = self.ast.push_expr(Expr::Binary {
+                left: expr,
                 operator,
-                right: Box::new(right)
-            }
+                right
+            })
         }
 
         Ok(expr)
     }
 
-    fn unary(&mut self) -> Result<Expr, LoxError> {
+    fn unary(&mut self) -> Result<ExprId, LoxError> {
         if self.matches(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary()?;
-            return Ok(Expr::Unary {
+            let right = self.nested(Self::unary)?;
+            return Ok(self.ast.push_expr(Expr::Unary {
                 operator,
-                right: Box::new(right)
-            });
+                right
+            }));
+        }
+
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<ExprId, LoxError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matches(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matches(&[TokenType::Dot]) {
+                let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expr = self.ast.push_expr(Expr::Get { object: expr, name });
+            } else {
+                break;
+            }
         }
 
-        self.primary()
+        Ok(expr)
     }
 
-    fn primary(&mut self) -> Result<Expr, LoxError> {
+    fn finish_call(&mut self, callee: ExprId) -> Result<ExprId, LoxError> {
+        let mut arguments = Vec::new();
+
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() == 255 {
+                    self.error("Can't have more than 255 arguments.", self.peek().span);
+                }
+                arguments.push(self.expression()?);
+
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+
+        Ok(self.ast.push_expr(Expr::Call { callee, paren, arguments }))
+    }
+
+    fn primary(&mut self) -> Result<ExprId, LoxError> {
         if self.matches(&[TokenType::False]) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: LoxLiteral::Bool(false)
-            });
+            }));
         }
         if self.matches(&[TokenType::True]) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: LoxLiteral::Bool(true)
-            });
+            }));
         }
         if self.matches(&[TokenType::Nil]) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: LoxLiteral::Nil
-            });
+            }));
+        }
+
+        if self.matches(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+            return Ok(self.ast.push_expr(Expr::Super { keyword, method }));
+        }
+
+        if self.matches(&[TokenType::This]) {
+            let keyword = self.previous();
+            return Ok(self.ast.push_expr(Expr::This { keyword }));
         }
 
         if self.matches(&[TokenType::Identifier]) {
-            return Ok(Expr::Variable { 
-                name: self.previous()
-            });
+            let name = self.previous();
+            return Ok(self.ast.push_expr(Expr::Variable { name }));
+        }
+
+        if self.matches(&[TokenType::Interpolation]) {
+            return self.interpolation();
         }
 
         if self.matches(&[TokenType::String, TokenType::Number]) {
-            let literal = self.previous().literal;
-            if let LoxType::String(str) = literal {
-                return Ok(Expr::Literal {
-                    value: LoxLiteral::String(str)
-                });
-            }
-            if let LoxType::Number(num) = literal {
-                return Ok(Expr::Literal {
-                    value: LoxLiteral::Number(num)
-                });
+            let value = match &self.token(self.previous()).literal {
+                LoxType::String(str) => Some(LoxLiteral::String(*str)),
+                LoxType::Int(int) => Some(LoxLiteral::Int(*int)),
+                LoxType::Number(num) => Some(LoxLiteral::Number(*num)),
+                _ => None
+            };
+            if let Some(value) = value {
+                return Ok(self.ast.push_expr(Expr::Literal { value }));
             }
         }
 
         if self.matches(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
-            if let Err(e) = self.consume(TokenType::RightParen, "Expect ')' after expression.") {
-                return Err(e);
-            };
-            return Ok(Expr::Grouping {
-                expression: Box::new(expr)
-            });
+            self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
+            return Ok(self.ast.push_expr(Expr::Grouping {
+                expression: expr
+            }));
         }
 
         Err(LoxError::ParseError {
             msg: "Expect expression.".into(),
-            line: self.peek().line
+            span: self.peek().span
         })
     }
 
+    /// `"a${b}c"` arrives as `Interpolation("a")`, the tokens of `b`, then
+    /// `String("c")`, and becomes `"a" + str(b) + "c"`. Each `str` is a unary
+    /// expression whose operator is the `Interpolation` token before it.
+    fn interpolation(&mut self) -> Result<ExprId, LoxError> {
+        let mut parts = Vec::new();
+
+        loop {
+            let part = self.previous();
+            let token = *self.token(part);
+
+            if let LoxType::String(str) = token.literal {
+                if str != Symbol::EMPTY {
+                    parts.push(self.ast.push_expr(Expr::Literal { value: LoxLiteral::String(str) }));
+                }
+            }
+            if token.r#type == TokenType::String {
+                break;
+            }
+
+            let right = self.expression()?;
+            parts.push(self.ast.push_expr(Expr::Unary { operator: part, right }));
+
+            if !self.matches(&[TokenType::Interpolation, TokenType::String]) {
+                return Err(LoxError::ParseError { msg: "Expect '}' after interpolated expression.".into(), span: self.peek().span });
+            }
+        }
+
+        let mut parts = parts.into_iter();
+        let mut expr = match parts.next() {
+            Some(first) => first,
+            None => self.ast.push_expr(Expr::Literal { value: LoxLiteral::String(Symbol::EMPTY) })
+        };
+
+        for right in parts {
+            let span = self.token(self.previous()).span;
+            let operator = self.ast.push_token(Token { r#type: TokenType::Plus, lexeme: None, literal: LoxType::Nil, span, doc: None });
+            expr = self.ast.push_expr(Expr::Binary { left: expr, operator, right });
+        }
+
+        Ok(expr)
+    }
+
     fn synchronize(&mut self) {
         self.advance();
 
         while !self.is_at_end() {
-            if self.previous().r#type == TokenType::Semicolon {
+            if self.token(self.previous()).r#type == TokenType::Semicolon {
                 return;
             }
 
@@ -351,8 +611,13 @@ impl Parser {
     }
 
     #[inline]
-    fn previous(&self) -> Token {
-        self.tokens[self.current - 1].clone()
+    fn previous(&self) -> TokenId {
+        TokenId::from_index(self.current - 1)
+    }
+
+    #[inline]
+    fn token(&self, id: TokenId) -> &Token {
+        self.ast.token(id)
     }
 
     #[inline]
@@ -361,24 +626,67 @@ impl Parser {
     }
 
     #[inline]
-    fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+    fn peek(&self) -> &Token {
+        &self.ast.tokens()[self.skip_comments(self.current)]
     }
 
-    fn advance(&mut self) -> Token {
+    fn advance(&mut self) -> TokenId {
         if !self.is_at_end() {
-            self.current += 1;
+            self.current = self.skip_comments(self.current) + 1;
         }
         self.previous()
     }
 
-    fn consume<'a>(&'a mut self, t: TokenType, msg: &'a str) -> Result<Token, LoxError> {
+    /// The scanner can't tell `a // b` from an operand followed by a
+    /// comment, so it leaves a `SlashSlash` and the rest of its line to the
+    /// parser. Between two factors they are an integer division, which
+    /// `divides` matches; everywhere else they are a comment, skipped here.
+    fn skip_comments(&self, mut index: usize) -> usize {
+        let tokens = self.ast.tokens();
+        while tokens[index].r#type == TokenType::SlashSlash {
+            let line = tokens[index].span.line;
+            index += 1;
+            while tokens[index].r#type != TokenType::Eof && tokens[index].span.line == line {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    fn divides(&mut self) -> bool {
+        if self.ast.tokens()[self.current].r#type == TokenType::SlashSlash {
+            self.current += 1;
+            return true;
+        }
+        false
+    }
+
+    /// Parses one level deeper, or fails once `MAX_NESTING` levels are open.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> Result<T, LoxError>) -> Result<T, LoxError> {
+        if self.nesting >= MAX_NESTING {
+            self.too_deep = true;
+            return Err(LoxError::ParseError { msg: "Too much nesting.".into(), span: self.peek().span });
+        }
+
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Records an error that doesn't need the parser to unwind, such as a
+    /// limit being exceeded.
+    fn error(&mut self, msg: &str, span: Span) {
+        self.errors.push(LoxError::ParseError { msg: msg.into(), span });
+    }
+
+    fn consume(&mut self, t: TokenType, msg: &str) -> Result<TokenId, LoxError> {
         if self.check(t) {
             return Ok(self.advance());
         }
         Err(LoxError::ParseError {
             msg: msg.into(),
-            line: self.peek().line,
+            span: self.peek().span,
         })
     }
-}
+}
\ No newline at end of file
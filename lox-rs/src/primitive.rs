@@ -0,0 +1,58 @@
+//! Truthiness, equality and printing of Lox values, shared by the
+//! interpreter, the VM and the optimizer so that all three agree on them.
+//! Each has its own value type, which `AsPrimitive` reduces to what these
+//! rules look at.
+
+use std::borrow::Cow;
+
+use crate::number::{format_float, Number};
+
+pub(crate) enum Primitive<'a> {
+    Nil,
+    Bool(bool),
+    Number(Number),
+    String(Cow<'a, str>),
+    /// A function, class or instance.
+    Object
+}
+
+pub(crate) trait AsPrimitive {
+    fn as_primitive(&self) -> Primitive<'_>;
+
+    /// Whether two objects are the same one. Only called when both are.
+    fn is_same_object(&self, other: &Self) -> bool;
+
+    /// How an object prints. Only called on objects.
+    fn object_name(&self) -> String;
+}
+
+/// `nil` and `false` are falsey; everything else is truthy.
+#[inline]
+pub(crate) fn is_truthy(value: &impl AsPrimitive) -> bool {
+    !matches!(value.as_primitive(), Primitive::Nil | Primitive::Bool(false))
+}
+
+/// Integers and floats compare by value, so `1 == 1.0`. Objects are equal
+/// only to themselves, and values of different types never are.
+pub(crate) fn is_equal<V: AsPrimitive>(l: &V, r: &V) -> bool {
+    match (l.as_primitive(), r.as_primitive()) {
+        (Primitive::Nil, Primitive::Nil) => true,
+        (Primitive::Bool(l), Primitive::Bool(r)) => l == r,
+        (Primitive::Number(l), Primitive::Number(r)) => l.equals(r),
+        (Primitive::String(l), Primitive::String(r)) => l == r,
+        (Primitive::Object, Primitive::Object) => l.is_same_object(r),
+        _ => false
+    }
+}
+
+/// What `print` and string interpolation show for a value.
+pub(crate) fn stringify(value: &impl AsPrimitive) -> String {
+    match value.as_primitive() {
+        Primitive::Nil => "nil".to_owned(),
+        Primitive::Bool(bool) => bool.to_string(),
+        Primitive::Number(Number::Int(int)) => int.to_string(),
+        Primitive::Number(Number::Float(float)) => format_float(float),
+        Primitive::String(str) => str.into_owned(),
+        Primitive::Object => value.object_name()
+    }
+}
@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{Ast, ExprId, LoxLiteral, StmtId, TokenId, VisitorExpr, VisitorStmt},
+    error::LoxError,
+    number::format_float,
+    token::{Token, TokenType},
+};
+
+/// Renders a parsed program as parenthesized prefix notation, one statement
+/// per line, with the bodies of blocks, functions and classes indented:
+///
+/// ```text
+/// (var a (+ 1 (* 2 3)))
+/// (fun add (x y)
+///   (return (+ x y)))
+/// ```
+pub struct AstPrinter {
+    depth: usize
+}
+
+impl Default for AstPrinter {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AstPrinter {
+    #[inline]
+    pub fn new() -> AstPrinter {
+        AstPrinter { depth: 0 }
+    }
+
+    pub fn print(&mut self, ast: &Ast) -> Result<String, LoxError> {
+        let lines = ast
+            .statements()
+            .iter()
+            .map(|&stmt| self.execute(ast, stmt))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lines.join("\n"))
+    }
+
+    fn parenthesize(&mut self, ast: &Ast, name: &str, exprs: &[ExprId]) -> Result<String, LoxError> {
+        let mut r = format!("({}", name);
+        for &expr in exprs {
+            r.push(' ');
+            r.push_str(&self.evaluate(ast, expr)?);
+        }
+        r.push(')');
+        Ok(r)
+    }
+
+    /// Prints `statements` on their own lines, one level deeper than the
+    /// enclosing node, and appends them to `head`.
+    fn nest(&mut self, ast: &Ast, mut head: String, statements: &[StmtId]) -> Result<String, LoxError> {
+        self.depth += 1;
+        let indent = "  ".repeat(self.depth);
+
+        for &stmt in statements {
+            match self.execute(ast, stmt) {
+                Ok(line) => {
+                    head.push('\n');
+                    head.push_str(&indent);
+                    head.push_str(&line);
+                }
+                Err(e) => {
+                    self.depth -= 1;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.depth -= 1;
+        head.push(')');
+        Ok(head)
+    }
+}
+
+#[inline]
+fn lexeme(token: &Token) -> Rc<str> {
+    token.name().as_rc()
+}
+
+fn operator(token: &Token) -> &'static str {
+    match token.r#type {
+        TokenType::Bang => "!",
+        TokenType::BangEqual => "!=",
+        TokenType::EqualEqual => "==",
+        TokenType::Greater => ">",
+        TokenType::GreaterEqual => ">=",
+        TokenType::Less => "<",
+        TokenType::LessEqual => "<=",
+        TokenType::Minus => "-",
+        TokenType::Plus => "+",
+        TokenType::Slash => "/",
+        TokenType::Star => "*",
+        TokenType::SlashSlash => "//",
+        TokenType::Percent => "%",
+        TokenType::And => "and",
+        TokenType::Or => "or",
+        TokenType::Interpolation => "str",
+        _ => "?"
+    }
+}
+
+impl VisitorExpr<String> for AstPrinter {
+    fn visit_assign_expr(&mut self, ast: &Ast, _id: ExprId, name: &Token, value: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, &format!("= {}", lexeme(name)), &[value])
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, self::operator(operator), &[left, right])
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, callee: ExprId, _paren: &Token, arguments: &[ExprId]) -> Result<String, LoxError> {
+        let mut exprs = vec![callee];
+        exprs.extend_from_slice(arguments);
+        self.parenthesize(ast, "call", &exprs)
+    }
+
+    fn visit_get_expr(&mut self, ast: &Ast, object: ExprId, name: &Token) -> Result<String, LoxError> {
+        let object = self.evaluate(ast, object)?;
+        Ok(format!("(. {} {})", object, lexeme(name)))
+    }
+
+    fn visit_grouping_expr(&mut self, ast: &Ast, expression: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, "group", &[expression])
+    }
+
+    fn visit_literal_expr(&mut self, _ast: &Ast, value: &LoxLiteral) -> Result<String, LoxError> {
+        Ok(match value {
+            LoxLiteral::String(str) => format!("{:?}", str),
+            LoxLiteral::Int(int) => int.to_string(),
+            LoxLiteral::Number(num) => format_float(*num),
+            LoxLiteral::Bool(bool) => bool.to_string(),
+            LoxLiteral::Nil => "nil".to_owned()
+        })
+    }
+
+    fn visit_logical_expr(&mut self, ast: &Ast, left: ExprId, operator: &Token, right: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, self::operator(operator), &[left, right])
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, object: ExprId, name: &Token, value: ExprId) -> Result<String, LoxError> {
+        let object = self.evaluate(ast, object)?;
+        let value = self.evaluate(ast, value)?;
+        Ok(format!("(.= {} {} {})", object, lexeme(name), value))
+    }
+
+    fn visit_super_expr(&mut self, _ast: &Ast, _id: ExprId, _keyword: &Token, method: &Token) -> Result<String, LoxError> {
+        Ok(format!("(super {})", lexeme(method)))
+    }
+
+    fn visit_this_expr(&mut self, _ast: &Ast, _id: ExprId, _keyword: &Token) -> Result<String, LoxError> {
+        Ok("this".to_owned())
+    }
+
+    fn visit_unary_expr(&mut self, ast: &Ast, operator: &Token, right: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, self::operator(operator), &[right])
+    }
+
+    fn visit_variable_expr(&mut self, _ast: &Ast, _id: ExprId, name: &Token) -> Result<String, LoxError> {
+        Ok(lexeme(name).to_string())
+    }
+}
+
+impl VisitorStmt<String> for AstPrinter {
+    fn visit_block_stmt(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<String, LoxError> {
+        self.nest(ast, "(block".to_owned(), statements)
+    }
+
+    fn visit_class_stmt(&mut self, ast: &Ast, name: &Token, superclass: Option<ExprId>, methods: &[StmtId]) -> Result<String, LoxError> {
+        let mut head = format!("(class {}", lexeme(name));
+        if let Some(superclass) = superclass {
+            head.push_str(" < ");
+            head.push_str(&self.evaluate(ast, superclass)?);
+        }
+        self.nest(ast, head, methods)
+    }
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, ";", &[expression])
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, name: &Token, params: &[TokenId], body: &[StmtId]) -> Result<String, LoxError> {
+        let params = params.iter().map(|&param| lexeme(ast.token(param))).collect::<Vec<_>>().join(" ");
+        self.nest(ast, format!("(fun {} ({})", lexeme(name), params), body)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Result<String, LoxError> {
+        let head = format!("(if {}", self.evaluate(ast, condition)?);
+        let mut branches = vec![then_branch];
+        branches.extend(else_branch);
+        self.nest(ast, head, &branches)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<String, LoxError> {
+        self.parenthesize(ast, "print", &[expression])
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _keyword: &Token, value: Option<ExprId>) -> Result<String, LoxError> {
+        self.parenthesize(ast, "return", value.as_slice())
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, name: &Token, initializer: Option<ExprId>) -> Result<String, LoxError> {
+        self.parenthesize(ast, &format!("var {}", lexeme(name)), initializer.as_slice())
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, condition: ExprId, body: StmtId) -> Result<String, LoxError> {
+        let head = format!("(while {}", self.evaluate(ast, condition)?);
+        self.nest(ast, head, &[body])
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Ast, Expr, ExprId, Local, LoxLiteral, Stmt, StmtId, TokenId, VisitorExpr, VisitorStmt},
+    error::LoxError,
+    intern::Symbol,
+    token::{Span, Token},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer
+}
+
+/// Static pass run between `Parser::parse` and `Interpreter::interpret`.
+/// It records the scope depth and slot of every local variable use in a side
+/// table on the `Ast`, so the interpreter never has to search for a binding
+/// by name. Slots are numbered in declaration order within each scope, which
+/// is the order the interpreter defines them in.
+///
+/// It also rejects uses of `this`, `super` and `return` that are
+/// grammatical but meaningless. These live here rather than in the parser
+/// because a program loaded from a cache file is resolved but never parsed.
+pub struct Resolver {
+    // Each local scope maps a name to its slot and whether its initializer
+    // has finished.
+    scopes: Vec<HashMap<Symbol, (usize, bool)>>,
+    // Indexed by `ExprId`; handed over to the `Ast` once resolution succeeds.
+    locals: Vec<Option<Local>>,
+    function_type: FunctionType,
+    class_type: ClassType,
+    // Every error found so far. Resolution carries on past each one so they
+    // can all be reported at once.
+    errors: Vec<LoxError>
+}
+
+impl Default for Resolver {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    #[inline]
+    pub fn new() -> Resolver {
+        Resolver { scopes: Vec::new(), locals: Vec::new(), function_type: FunctionType::None, class_type: ClassType::None, errors: Vec::new() }
+    }
+
+    pub fn resolve(&mut self, mut ast: Ast) -> Result<Ast, Vec<LoxError>> {
+        self.locals = vec![None; ast.exprs().len()];
+        if let Err(e) = self.resolve_all(&ast, ast.statements()) {
+            self.errors.push(e);
+        }
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors))
+        }
+
+        ast.set_locals(std::mem::take(&mut self.locals));
+        Ok(ast)
+    }
+
+    fn resolve_all(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<(), LoxError> {
+        statements.iter().try_for_each(|&stmt| self.execute(ast, stmt))
+    }
+
+    fn error(&mut self, msg: &str, span: Span) {
+        self.errors.push(LoxError::ResolveError { msg: msg.into(), span });
+    }
+
+    #[inline]
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    #[inline]
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let lexeme = name.name();
+            if scope.contains_key(&lexeme) {
+                self.error("Already a variable with this name in this scope.", name.span);
+                return;
+            }
+            scope.insert(lexeme, (scope.len(), false));
+        }
+    }
+
+    fn define(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let slot = scope.get(&name).map_or(scope.len(), |&(slot, _)| slot);
+            scope.insert(name, (slot, true));
+        }
+    }
+
+    fn resolve_local(&mut self, id: ExprId, name: Symbol) {
+        self.locals[id.index()] = self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| scope.get(&name).map(|&(slot, _)| Local { depth, slot }));
+    }
+
+    fn resolve_function(&mut self, ast: &Ast, params: &[TokenId], body: &[StmtId], function_type: FunctionType) -> Result<(), LoxError> {
+        let enclosing = std::mem::replace(&mut self.function_type, function_type);
+        self.begin_scope();
+
+        let result = params
+            .iter()
+            .try_for_each(|&param| {
+                let param = ast.token(param);
+                self.declare(param);
+                self.define(param.name());
+                Ok(())
+            })
+            .and_then(|_| self.resolve_all(ast, body));
+
+        self.end_scope();
+        self.function_type = enclosing;
+        result
+    }
+}
+
+impl VisitorExpr<()> for Resolver {
+    fn visit_assign_expr(&mut self, ast: &Ast, id: ExprId, name: &Token, value: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, value)?;
+        self.resolve_local(id, name.name());
+        Ok(())
+    }
+
+    fn visit_binary_expr(&mut self, ast: &Ast, left: ExprId, _operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, left)?;
+        self.evaluate(ast, right)
+    }
+
+    fn visit_call_expr(&mut self, ast: &Ast, callee: ExprId, _paren: &Token, arguments: &[ExprId]) -> Result<(), LoxError> {
+        self.evaluate(ast, callee)?;
+        arguments.iter().try_for_each(|&argument| self.evaluate(ast, argument))
+    }
+
+    #[inline]
+    fn visit_get_expr(&mut self, ast: &Ast, object: ExprId, _name: &Token) -> Result<(), LoxError> {
+        self.evaluate(ast, object)
+    }
+
+    #[inline]
+    fn visit_grouping_expr(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)
+    }
+
+    #[inline]
+    fn visit_literal_expr(&mut self, _ast: &Ast, _value: &LoxLiteral) -> Result<(), LoxError> {
+        Ok(())
+    }
+
+    fn visit_logical_expr(&mut self, ast: &Ast, left: ExprId, _operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, left)?;
+        self.evaluate(ast, right)
+    }
+
+    fn visit_set_expr(&mut self, ast: &Ast, object: ExprId, _name: &Token, value: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, value)?;
+        self.evaluate(ast, object)
+    }
+
+    #[inline]
+    fn visit_super_expr(&mut self, _ast: &Ast, id: ExprId, keyword: &Token, _method: &Token) -> Result<(), LoxError> {
+        match self.class_type {
+            ClassType::None => self.error("Can't use 'super' outside of a class.", keyword.span),
+            ClassType::Class => self.error("Can't use 'super' in a class with no superclass.", keyword.span),
+            ClassType::Subclass => self.resolve_local(id, Symbol::SUPER)
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn visit_this_expr(&mut self, _ast: &Ast, id: ExprId, keyword: &Token) -> Result<(), LoxError> {
+        if self.class_type == ClassType::None {
+            self.error("Can't use 'this' outside of a class.", keyword.span);
+            return Ok(());
+        }
+
+        self.resolve_local(id, Symbol::THIS);
+        Ok(())
+    }
+
+    #[inline]
+    fn visit_unary_expr(&mut self, ast: &Ast, _operator: &Token, right: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, right)
+    }
+
+    fn visit_variable_expr(&mut self, _ast: &Ast, id: ExprId, name: &Token) -> Result<(), LoxError> {
+        let lexeme = name.name();
+
+        if let Some((_, false)) = self.scopes.last().and_then(|scope| scope.get(&lexeme)) {
+            self.error("Can't read local variable in its own initializer.", name.span);
+        }
+
+        self.resolve_local(id, lexeme);
+        Ok(())
+    }
+}
+
+impl VisitorStmt<()> for Resolver {
+    fn visit_block_stmt(&mut self, ast: &Ast, statements: &[StmtId]) -> Result<(), LoxError> {
+        self.begin_scope();
+        let result = self.resolve_all(ast, statements);
+        self.end_scope();
+        result
+    }
+
+    fn visit_class_stmt(&mut self, ast: &Ast, name: &Token, superclass: Option<ExprId>, methods: &[StmtId]) -> Result<(), LoxError> {
+        self.declare(name);
+        self.define(name.name());
+
+        if let Some(expr) = superclass {
+            if let Expr::Variable { name: superclass_name } = ast.expr(expr) {
+                let superclass_name = ast.token(*superclass_name);
+                if superclass_name.lexeme == name.lexeme {
+                    self.error("A class can't inherit from itself.", superclass_name.span);
+                }
+            }
+
+            self.evaluate(ast, expr)?;
+            self.begin_scope();
+            self.define(Symbol::SUPER);
+        }
+
+        let class_type = if superclass.is_some() { ClassType::Subclass } else { ClassType::Class };
+        let enclosing = std::mem::replace(&mut self.class_type, class_type);
+        self.begin_scope();
+        self.define(Symbol::THIS);
+
+        let result = methods.iter().try_for_each(|&method| match ast.stmt(method) {
+            Stmt::Function { name, params, body } => {
+                let function_type = if ast.token(*name).lexeme == Some(Symbol::INIT) {
+                    FunctionType::Initializer
+                } else {
+                    FunctionType::Method
+                };
+                self.resolve_function(ast, params, body, function_type)
+            }
+            _ => Ok(())
+        });
+
+        self.end_scope();
+        self.class_type = enclosing;
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
+
+        result
+    }
+
+    #[inline]
+    fn visit_expression_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, name: &Token, params: &[TokenId], body: &[StmtId]) -> Result<(), LoxError> {
+        self.declare(name);
+        self.define(name.name());
+
+        self.resolve_function(ast, params, body, FunctionType::Function)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId>) -> Result<(), LoxError> {
+        self.evaluate(ast, condition)?;
+        self.execute(ast, then_branch)?;
+        match else_branch {
+            Some(stmt) => self.execute(ast, stmt),
+            None => Ok(())
+        }
+    }
+
+    #[inline]
+    fn visit_print_stmt(&mut self, ast: &Ast, expression: ExprId) -> Result<(), LoxError> {
+        self.evaluate(ast, expression)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, keyword: &Token, value: Option<ExprId>) -> Result<(), LoxError> {
+        if self.function_type == FunctionType::None {
+            self.error("Can't return from top-level code.", keyword.span);
+        } else if value.is_some() && self.function_type == FunctionType::Initializer {
+            self.error("Can't return a value from an initializer.", keyword.span);
+        }
+
+        match value {
+            Some(expr) => self.evaluate(ast, expr),
+            None => Ok(())
+        }
+    }
+
+    fn visit_var_stmt(&mut self, ast: &Ast, name: &Token, initializer: Option<ExprId>) -> Result<(), LoxError> {
+        self.declare(name);
+
+        if let Some(expr) = initializer {
+            self.evaluate(ast, expr)?;
+        }
+
+        self.define(name.name());
+        Ok(())
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, condition: ExprId, body: StmtId) -> Result<(), LoxError> {
+        self.evaluate(ast, condition)?;
+        self.execute(ast, body)
+    }
+}
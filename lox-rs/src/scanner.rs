@@ -1,35 +1,100 @@
-use crate::{token::{Token, LoxType, TokenType}, error::LoxError};
+use unicode_xid::UnicodeXID;
+
+use crate::{token::{Token, LoxType, TokenType, Span}, error::LoxError, intern::Symbol};
 
 pub struct Scanner {
     source: Vec<u8>,
+    start: usize,
     current: usize,
-    line: u16
+    line: u32,
+    // 1-based column, in characters, of the byte at `current`.
+    column: u32,
+    // Line and column of the lexeme starting at `start`.
+    start_line: u32,
+    start_column: u32,
+    // One entry per `${` still open: how many `{` inside it are unclosed,
+    // and where it began.
+    interpolations: Vec<(usize, Span)>,
+    // `///` lines seen since the last token, waiting to be attached to the
+    // next one.
+    doc: Option<String>
+}
+
+/// What to go back to if the rest of a line after a `SlashSlash` turns out
+/// not to be code.
+struct Tentative {
+    current: usize,
+    line: u32,
+    column: u32,
+    tokens: usize,
+    errors: usize,
+    is_error: bool,
+    interpolations: Vec<(usize, Span)>,
+    doc: Option<String>
 }
 
 impl Scanner {
     #[inline]
     pub fn new(source: Vec<u8>) -> Scanner {
-        Scanner { source, current: 0, line: 1 }
+        Scanner { source, start: 0, current: 0, line: 1, column: 1, start_line: 1, start_column: 1, interpolations: Vec::new(), doc: None }
     }
 
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<LoxError>> {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
         let mut is_error = false;
+        let mut tentative = None;
 
         while !self.is_at_end() {
+            self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            let count = tokens.len();
+
             let byte = self.advance();
             match byte {
-                b'(' => tokens.push(Token{r#type: TokenType::LeftParen,     lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b')' => tokens.push(Token{r#type: TokenType::RightParen,    lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'{' => tokens.push(Token{r#type: TokenType::LeftBrace,     lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'}' => tokens.push(Token{r#type: TokenType::RightBrace,    lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b',' => tokens.push(Token{r#type: TokenType::Comma,         lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'.' => tokens.push(Token{r#type: TokenType::Dot,           lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'-' => tokens.push(Token{r#type: TokenType::Minus,         lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'+' => tokens.push(Token{r#type: TokenType::Plus,          lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b';' => tokens.push(Token{r#type: TokenType::Semicolon,     lexeme: None, literal: LoxType::Nil, line: self.line}),
-                b'*' => tokens.push(Token{r#type: TokenType::Star,          lexeme: None, literal: LoxType::Nil, line: self.line}),
+                b'(' => tokens.push(Token{r#type: TokenType::LeftParen,     lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b')' => tokens.push(Token{r#type: TokenType::RightParen,    lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'{' => {
+                    if let Some((depth, _)) = self.interpolations.last_mut() {
+                        *depth += 1;
+                    }
+                    tokens.push(Token{r#type: TokenType::LeftBrace, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
+                }
+                b'}' => {
+                    match self.interpolations.last_mut() {
+                        // Closes a `${`: the string it interrupted carries on.
+                        Some((0, _)) => {
+                            if let Some((_, open)) = self.interpolations.pop() {
+                                // Nothing was scanned since the `${` if the
+                                // last token is the string part it ended.
+                                if tokens.last().is_some_and(|token| token.r#type == TokenType::Interpolation && token.span.end == open.end) {
+                                    is_error = true;
+                                    errors.push(LoxError::LexError{char: ' ', msg: "字符串插值不能为空.".into(), span: Span { end: self.current, ..open }});
+                                }
+                            }
+                            match self.string() {
+                                Ok((token_type, str)) => tokens.push(Token{r#type: token_type, lexeme: None, literal: str, span: self.span(), doc: None}),
+                                Err(e) => {
+                                    is_error = true;
+                                    errors.push(e);
+                                }
+                            }
+                        }
+                        Some((depth, _)) => {
+                            *depth -= 1;
+                            tokens.push(Token{r#type: TokenType::RightBrace, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
+                        }
+                        None => tokens.push(Token{r#type: TokenType::RightBrace, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None})
+                    }
+                }
+                b',' => tokens.push(Token{r#type: TokenType::Comma,         lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'.' => tokens.push(Token{r#type: TokenType::Dot,           lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'-' => tokens.push(Token{r#type: TokenType::Minus,         lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'+' => tokens.push(Token{r#type: TokenType::Plus,          lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b';' => tokens.push(Token{r#type: TokenType::Semicolon,     lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'*' => tokens.push(Token{r#type: TokenType::Star,          lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
+                b'%' => tokens.push(Token{r#type: TokenType::Percent,       lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None}),
 
                 b'!' => {
                     let token_type = if self.matching(b'=') {
@@ -37,7 +102,7 @@ impl Scanner {
                     } else {
                         TokenType::Bang
                     };
-                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, line: self.line});
+                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
                 }
                 b'=' => {
                     let token_type = if self.matching(b'=') {
@@ -45,7 +110,7 @@ impl Scanner {
                     } else {
                         TokenType::Equal
                     };
-                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, line: self.line});
+                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
                 }
                 b'<' => {
                     let token_type = if self.matching(b'=') {
@@ -53,7 +118,7 @@ impl Scanner {
                     } else {
                         TokenType::Less
                     };
-                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, line: self.line});
+                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
                 }
                 b'>' => {
                     let token_type = if self.matching(b'=') {
@@ -61,26 +126,70 @@ impl Scanner {
                     } else {
                         TokenType::Greater
                     };
-                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, line: self.line});
+                    tokens.push(Token{r#type: token_type, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
                 }
                 b'/' => {
                     if self.matching(b'/') {
-                        while self.peek() != b'\n' && !self.is_at_end() {
-                            self.advance();
+                        // `///` starts a doc comment, but `////` doesn't.
+                        let is_doc = self.peek() == b'/' && self.peek_next() != b'/';
+                        let follows_operand = tokens.last().is_some_and(|token: &Token| token.span.line == self.line && matches!(token.r#type,
+                            TokenType::Number | TokenType::String | TokenType::Identifier | TokenType::RightParen |
+                            TokenType::True | TokenType::False | TokenType::Nil | TokenType::This));
+
+                        if follows_operand && self.peek() != b'/' {
+                            // Integer division, or a comment after an
+                            // operand: only the parser can tell. The rest of
+                            // the line is scanned as code, and goes back to
+                            // being a comment if it doesn't scan.
+                            if tentative.is_none() {
+                                tentative = Some(Tentative {
+                                    current: self.current,
+                                    line: self.line,
+                                    column: self.column,
+                                    tokens: tokens.len(),
+                                    errors: errors.len(),
+                                    is_error,
+                                    interpolations: self.interpolations.clone(),
+                                    doc: self.doc.clone()
+                                });
+                            }
+                            tokens.push(Token{r#type: TokenType::SlashSlash, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
+                        } else {
+                            if is_doc {
+                                self.advance();
+                            }
+
+                            let text_start = self.current;
+                            is_error |= self.line_comment(&mut errors);
+
+                            if is_doc {
+                                self.doc_line(text_start);
+                            }
+                        }
+                    } else if self.matching(b'*') {
+                        if let Err(e) = self.block_comment() {
+                            is_error = true;
+                            errors.push(e);
                         }
                     } else {
-                        tokens.push(Token{r#type: TokenType::Slash, lexeme: None, literal: LoxType::Nil, line: self.line});
+                        tokens.push(Token{r#type: TokenType::Slash, lexeme: None, literal: LoxType::Nil, span: self.span(), doc: None});
                     }
                 }
 
                 b' ' |
                 b'\r'|
                 b'\t' => continue,
-                b'\n' => self.line += 1,
+                b'\n' => {
+                    // After a rollback the `\n` is scanned again.
+                    let is_rollback = tentative.take().is_some_and(|tentative| self.rollback(tentative, &mut tokens, &mut errors, &mut is_error));
+                    if !is_rollback {
+                        self.newline();
+                    }
+                }
 
                 b'"' => {
                     match self.string() {
-                        Ok(str) => tokens.push(Token{r#type: TokenType::String, lexeme: None, literal: str, line: self.line}),
+                        Ok((token_type, str)) => tokens.push(Token{r#type: token_type, lexeme: None, literal: str, span: self.span(), doc: None}),
                         Err(e) => {
                             is_error = true;
                             errors.push(e);
@@ -91,55 +200,170 @@ impl Scanner {
                 _ => {
                     if self.is_digit(byte) {
                         match self.number() {
-                            Ok(num) => tokens.push(Token{r#type: TokenType::Number, lexeme: None, literal: num, line: self.line}),
+                            Ok(num) => tokens.push(Token{r#type: TokenType::Number, lexeme: None, literal: num, span: self.span(), doc: None}),
                             Err(e) => {
                                 is_error = true;
                                 errors.push(e);
                             }
                         }
                     } else if self.is_alpha(byte) {
-                        match self.identifier() {
-                            Ok((token_type, id, lox_type)) => {
-                                tokens.push(Token{r#type: token_type, lexeme: Some(id), literal: lox_type, line: self.line});
+                        self.push_identifier(&mut tokens);
+                    } else if byte.is_ascii() {
+                        is_error = true;
+                        errors.push(LoxError::LexError{msg: "未知的词素.".into(), char: byte as char, span: self.span()});
+                    } else {
+                        // The first byte of a multi-byte character.
+                        self.rewind((self.start, self.start_column));
+                        match self.advance_char() {
+                            Ok(c) if c.is_xid_start() => self.push_identifier(&mut tokens),
+                            Ok(c) => {
+                                is_error = true;
+                                errors.push(LoxError::LexError{msg: "未知的词素.".into(), char: c, span: self.span()});
                             }
                             Err(e) => {
                                 is_error = true;
                                 errors.push(e);
                             }
                         }
-                    } else {
-                        is_error = true;
-                        errors.push(LoxError::LexError{msg: "未知的词素.".into(), char: byte as char, line: self.line});
                     }
                 }
             }
+
+            if tokens.len() > count {
+                if let Some(doc) = self.doc.take() {
+                    tokens[count].doc = Some(Symbol::intern(&doc));
+                }
+            }
+        }
+
+        if let Some(tentative) = tentative {
+            self.rollback(tentative, &mut tokens, &mut errors, &mut is_error);
+        }
+
+        for (_, span) in self.interpolations.drain(..) {
+            is_error = true;
+            errors.push(LoxError::LexError{char: ' ', msg: "字符串插值没有结束.".into(), span});
         }
 
         if is_error {
             return Err(errors)
         }
 
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+
         tokens.push(Token {
             r#type: TokenType::Eof,
             lexeme: None,
             literal: LoxType::Nil,
-            line: self.line
+            span: self.span(),
+            doc: self.doc.take().map(|doc| Symbol::intern(&doc))
         });
 
         Ok(tokens)
     }
 
+    /// Skips the rest of a line comment. Returns whether it held invalid
+    /// UTF-8, which is reported like anywhere else.
+    fn line_comment(&mut self, errors: &mut Vec<LoxError>) -> bool {
+        let mut is_error = false;
+        while self.peek() != b'\n' && !self.is_at_end() {
+            if let Err(e) = self.advance_char() {
+                is_error = true;
+                errors.push(e);
+            }
+        }
+        is_error
+    }
+
+    /// Called at the end of a line that a `SlashSlash` was scanned on. The
+    /// rest of the line stays code only if it scanned without errors,
+    /// without running onto the next line and without leaving a `${` open;
+    /// otherwise it is scanned again as a comment, and this returns true.
+    fn rollback(&mut self, tentative: Tentative, tokens: &mut Vec<Token>, errors: &mut Vec<LoxError>, is_error: &mut bool) -> bool {
+        if errors.len() == tentative.errors && self.line == tentative.line && self.interpolations.len() <= tentative.interpolations.len() {
+            return false;
+        }
+
+        tokens.truncate(tentative.tokens);
+        errors.truncate(tentative.errors);
+        *is_error = tentative.is_error;
+        self.interpolations = tentative.interpolations;
+        self.doc = tentative.doc;
+        self.line = tentative.line;
+        self.rewind((tentative.current, tentative.column));
+
+        *is_error |= self.line_comment(errors);
+        true
+    }
+
+    #[inline]
+    fn span(&self) -> Span {
+        Span { start: self.start, end: self.current, line: self.start_line, column: self.start_column }
+    }
+
+    /// The current byte offset and its column, to come back to with
+    /// `rewind` or to start an error's span at.
+    #[inline]
+    fn position(&self) -> (usize, u32) {
+        (self.current, self.column)
+    }
+
+    /// Goes back to a `position` on the current line.
+    #[inline]
+    fn rewind(&mut self, (current, column): (usize, u32)) {
+        self.current = current;
+        self.column = column;
+    }
+
+    /// Called once the `\n` ending a line has been consumed.
+    #[inline]
+    fn newline(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
     #[inline]
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
+    /// Consumes one byte, which counts as a column of its own. Only used
+    /// for ASCII; other characters go through `advance_char`.
     #[inline]
     fn advance(&mut self) -> u8 {
         self.current += 1;
+        self.column += 1;
         *self.source.get(self.current - 1).unwrap()
     }
 
+    /// Consumes one whole character, decoding it from UTF-8. An invalid or
+    /// truncated sequence is consumed too, and reported. Either way it takes
+    /// one column, the way a lossy decoding displays it.
+    fn advance_char(&mut self) -> Result<char, LoxError> {
+        let start = self.position();
+        let bytes = &self.source[self.current..self.source.len().min(self.current + 4)];
+
+        // Only the first character matters; the window may cut off the next.
+        let valid = match std::str::from_utf8(bytes) {
+            Ok(valid) => valid,
+            Err(e) => match std::str::from_utf8(&bytes[..e.valid_up_to()]) {
+                Ok(valid) if !valid.is_empty() => valid,
+                _ => {
+                    self.current += e.error_len().unwrap_or(bytes.len());
+                    self.column += 1;
+                    return Err(self.error_at(start, ' ', "无效的 UTF-8 编码."));
+                }
+            }
+        };
+
+        let c = valid.chars().next().unwrap_or_default();
+        self.current += c.len_utf8();
+        self.column += 1;
+        Ok(c)
+    }
+
     #[inline]
     fn peek(&self) -> u8 {
         if self.is_at_end() {
@@ -167,79 +391,333 @@ impl Scanner {
             return false
         }
 
-        self.current += 1;
+        self.advance();
         true
     }
 
-    fn string<'a>(&mut self) -> Result<LoxType, LoxError> {
-        let start_index = self.current;
+    /// Skips a block comment whose `/*` has been consumed. Block comments
+    /// nest, so each `/*` inside needs a `*/` of its own.
+    fn block_comment(&mut self) -> Result<(), LoxError> {
+        let mut depth = 1;
+        // Reported once the whole comment has been consumed, like in `string`.
+        let mut invalid = None;
+
+        while depth > 0 {
+            if self.is_at_end() {
+                let span = Span { start: self.start, end: self.start + 2, line: self.start_line, column: self.start_column };
+                return Err(LoxError::LexError{char: ' ', msg: "块注释没有结束.".into(), span});
+            }
+
+            match (self.peek(), self.peek_next()) {
+                (b'/', b'*') => {
+                    self.advance();
+                    self.advance();
+                    depth += 1;
+                }
+                (b'*', b'/') => {
+                    self.advance();
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => match self.advance_char() {
+                    Ok('\n') => self.newline(),
+                    Ok(_) => (),
+                    Err(e) => {
+                        invalid.get_or_insert(e);
+                    }
+                }
+            }
+        }
+
+        match invalid {
+            Some(e) => Err(e),
+            None => Ok(())
+        }
+    }
+
+    /// Adds the doc comment line starting at `text_start` to the ones
+    /// waiting for the next token.
+    fn doc_line(&mut self, text_start: usize) {
+        let text = String::from_utf8_lossy(&self.source[text_start..self.current]);
+        let text = text.strip_prefix(' ').unwrap_or(&text).trim_end_matches('\r');
 
-        while self.peek() != b'"' && !self.is_at_end() {
-            if self.peek() == b'\n' {
-                self.line += 1;
+        match &mut self.doc {
+            Some(doc) => {
+                doc.push('\n');
+                doc.push_str(text);
             }
-            self.advance();
+            None => self.doc = Some(text.to_owned())
         }
+    }
 
+    /// Scans string contents up to the closing quote, or up to a `${`, which
+    /// makes the part so far an `Interpolation` token. Scanning resumes here
+    /// at the `}` that closes the interpolation.
+    fn string(&mut self) -> Result<(TokenType, LoxType), LoxError> {
+        let mut str = String::new();
+        // Reported once the whole part has been consumed, so scanning
+        // carries on after it.
+        let mut invalid = None;
+
+        let token_type = loop {
+            if self.is_at_end() {
+                return Err(LoxError::LexError{char: ' ', msg: "不是一串完整的字符串.".into(), span: self.span()})
+            }
+
+            match self.peek() {
+                b'"' => {
+                    self.advance();
+                    break TokenType::String;
+                }
+                b'$' if self.peek_next() == b'{' => {
+                    let (start, column) = self.position();
+                    self.advance();
+                    self.advance();
+                    self.interpolations.push((0, Span { start, end: self.current, line: self.line, column }));
+                    break TokenType::Interpolation;
+                }
+                b'\\' => match self.escape() {
+                    Ok(c) => str.push(c),
+                    Err(e) => {
+                        invalid.get_or_insert(e);
+                    }
+                },
+                _ => match self.advance_char() {
+                    Ok(c) => {
+                        if c == '\n' {
+                            self.newline();
+                        }
+                        str.push(c);
+                    }
+                    Err(e) => {
+                        invalid.get_or_insert(e);
+                    }
+                }
+            }
+        };
+
+        match invalid {
+            Some(e) => Err(e),
+            None => Ok((token_type, LoxType::String(Symbol::intern(&str))))
+        }
+    }
+
+    /// Decodes one of `\n \t \" \\ \$ \u{...}`, starting at the backslash.
+    fn escape(&mut self) -> Result<char, LoxError> {
+        let start = self.position();
+        self.advance();
         if self.is_at_end() {
-            return Err(LoxError::LexError{char: ' ', msg: "不是一串完整的字符串.".into(), line: self.line})
+            return Err(self.error_at(start, ' ', "无效的转义序列."));
         }
 
-        let str = String::from_utf8(self.source[start_index..self.current].to_vec()).unwrap();
+        let c = match self.advance_char()? {
+            'n' => '\n',
+            't' => '\t',
+            '"' => '"',
+            '\\' => '\\',
+            '$' => '$',
+            'u' => {
+                let digits = if self.matching(b'{') {
+                    let digits_start = self.current;
+                    while self.peek().is_ascii_hexdigit() {
+                        self.advance();
+                    }
+                    let digits = String::from_utf8_lossy(&self.source[digits_start..self.current]).into_owned();
+                    if self.matching(b'}') { Some(digits) } else { None }
+                } else {
+                    None
+                };
+
+                match digits.as_deref().filter(|digits| (1..=6).contains(&digits.len())) {
+                    Some(digits) => match u32::from_str_radix(digits, 16).ok().and_then(char::from_u32) {
+                        Some(c) => c,
+                        None => return Err(self.error_at(start, ' ', "不是有效的 Unicode 码点."))
+                    },
+                    None => return Err(self.error_at(start, ' ', "Unicode 转义应写成 \\u{...}."))
+                }
+            }
+            c => {
+                let error = self.error_at(start, if c.is_control() { ' ' } else { c }, "无效的转义序列.");
+                if c == '\n' {
+                    self.newline();
+                }
+                return Err(error);
+            }
+        };
 
-        self.advance();
+        Ok(c)
+    }
+
+    /// An error spanning from the `position` `start` to the current one, which
+    /// must be on the same line.
+    #[inline]
+    fn error_at(&self, (start, column): (usize, u32), char: char, msg: &str) -> LoxError {
+        let span = Span { start, end: self.current, line: self.line, column };
+        LoxError::LexError{char, msg: msg.into(), span}
+    }
 
-        Ok(LoxType::String(str))
+    /// Scans a number literal whose first digit has been consumed: decimal
+    /// with an optional fraction and exponent, or `0x`, `0b` or `0o` followed
+    /// by hexadecimal, binary or octal digits. Digits may be separated by `_`.
+    /// Literals without a fraction or exponent are integers.
+    fn number(&mut self) -> Result<LoxType, LoxError> {
+        let radix = match (self.source[self.start], self.peek()) {
+            (b'0', b'x' | b'X') => Some((16, "十六进制")),
+            (b'0', b'b' | b'B') => Some((2, "二进制")),
+            (b'0', b'o' | b'O') => Some((8, "八进制")),
+            _ => None
+        };
+
+        let result = match radix {
+            Some((radix, name)) => self.radix_number(radix, name),
+            None => self.decimal_number()
+        };
+
+        // Skip whatever is left of a malformed literal, so that it is
+        // reported once rather than as a trail of stray tokens.
+        if result.is_err() {
+            while self.peek().is_ascii_alphanumeric() || self.peek() == b'_' {
+                self.advance();
+            }
+        }
+        result
     }
 
-    fn number<'a>(&mut self) -> Result<LoxType, LoxError> {
-        let start_index = self.current - 1;
-        while self.is_digit(self.peek()) {
+    fn decimal_number(&mut self) -> Result<LoxType, LoxError> {
+        let mut text = String::new();
+        self.rewind((self.start, self.start_column));
+        self.digits(10, &mut text)?;
+
+        if self.peek() == b'.' && self.is_digit(self.peek_next()) {
             self.advance();
+            text.push('.');
+            self.digits(10, &mut text)?;
         }
 
-        if self.peek() == b'.' && self.is_digit(self.peek_next()) {
+        if matches!(self.peek(), b'e' | b'E') {
+            let exponent = self.position();
             self.advance();
-            
-            while self.is_digit(self.peek()) {
-                self.advance();
+            text.push('e');
+            if matches!(self.peek(), b'+' | b'-') {
+                text.push(self.advance() as char);
             }
+            if !self.is_digit(self.peek()) {
+                return Err(self.error_at(exponent, ' ', "指数部分缺少数字."));
+            }
+            self.digits(10, &mut text)?;
+        }
+
+        // Without a fraction or an exponent it is an integer.
+        if !text.contains(['.', 'e']) {
+            return match text.parse::<i64>() {
+                Ok(int) => Ok(LoxType::Int(int)),
+                Err(_) => Err(LoxError::LexError{char: ' ', msg: "整数超出范围.".into(), span: self.span()})
+            };
         }
 
-        let num = String::from_utf8(self.source[start_index..self.current].to_vec()).unwrap();
-        
-        match num.parse::<f64>() {
-            Ok(num) => Ok(LoxType::Number(num)),
-            Err(_) => Err(LoxError::LexError{char: ' ', msg: "不是一串有效的数字.".into(), line: self.line})
+        match text.parse::<f64>() {
+            Ok(num) if num.is_finite() => Ok(LoxType::Number(num)),
+            Ok(_) => Err(LoxError::LexError{char: ' ', msg: "数字超出范围.".into(), span: self.span()}),
+            Err(_) => Err(LoxError::LexError{char: ' ', msg: "不是一串有效的数字.".into(), span: self.span()})
         }
     }
 
-    fn identifier<'a>(&mut self) -> Result<(TokenType, String, LoxType), LoxError> {
-        let start_index = self.current - 1;
-        while self.is_alpha_numeric(self.peek()) {
-            self.advance();
+    /// Scans the digits after a `0x`, `0b` or `0o` prefix, which is still to
+    /// be consumed.
+    fn radix_number(&mut self, radix: u32, name: &str) -> Result<LoxType, LoxError> {
+        self.advance();
+        let mut text = String::new();
+        self.digits(radix, &mut text)?;
+
+        // A letter or digit straight after the literal can only be a digit
+        // that doesn't belong to this base.
+        if self.peek().is_ascii_alphanumeric() {
+            let start = self.position();
+            let c = self.advance() as char;
+            return Err(self.error_at(start, c, &format!("不是有效的{}数字.", name)));
+        }
+
+        if text.is_empty() {
+            return Err(LoxError::LexError{char: ' ', msg: format!("{}字面量缺少数字.", name).into(), span: self.span()});
+        }
+
+        match i64::from_str_radix(&text, radix) {
+            Ok(int) => Ok(LoxType::Int(int)),
+            Err(_) => Err(LoxError::LexError{char: ' ', msg: "整数超出范围.".into(), span: self.span()})
+        }
+    }
+
+    /// Consumes a run of digits in `radix`, appending them to `text`. A `_`
+    /// is allowed only between two digits.
+    fn digits(&mut self, radix: u32, text: &mut String) -> Result<(), LoxError> {
+        loop {
+            let c = self.peek() as char;
+            if c.is_digit(radix) {
+                self.advance();
+                text.push(c);
+            } else if c == '_' {
+                let start = self.position();
+                let before = (self.source[self.current - 1] as char).is_digit(radix);
+                self.advance();
+                if !before || !(self.peek() as char).is_digit(radix) {
+                    return Err(self.error_at(start, '_', "只能出现在两个数字之间."));
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn push_identifier(&mut self, tokens: &mut Vec<Token>) {
+        let (token_type, id, lox_type) = self.identifier();
+        tokens.push(Token{r#type: token_type, lexeme: Some(id), literal: lox_type, span: self.span(), doc: None});
+    }
+
+    /// Scans the rest of an identifier whose first character has been
+    /// consumed. Identifiers follow Unicode's XID rules, plus `_`.
+    fn identifier(&mut self) -> (TokenType, Symbol, LoxType) {
+        let start_index = self.start;
+        loop {
+            let byte = self.peek();
+            if byte.is_ascii() {
+                if !self.is_alpha_numeric(byte) {
+                    break;
+                }
+                self.advance();
+            } else {
+                let start = self.position();
+                match self.advance_char() {
+                    Ok(c) if c.is_xid_continue() => (),
+                    // Left for the main loop to report.
+                    _ => {
+                        self.rewind(start);
+                        break;
+                    }
+                }
+            }
         }
 
-        let id = String::from_utf8(self.source[start_index..self.current].to_vec()).unwrap();
-        match id.as_str() {
-            "and"       =>    Ok((TokenType::And, id, LoxType::Nil)),
-            "class"     =>    Ok((TokenType::Class, id, LoxType::Nil)),
-            "else"      =>    Ok((TokenType::Else, id, LoxType::Nil)),
-            "false"     =>    Ok((TokenType::False, id, LoxType::Nil)),
-            "for"       =>    Ok((TokenType::For, id, LoxType::Nil)),
-            "fun"       =>    Ok((TokenType::Fun, id, LoxType::Nil)),
-            "if"        =>    Ok((TokenType::If, id, LoxType::Nil)),
-            "nil"       =>    Ok((TokenType::Nil, id, LoxType::Nil)),
-            "or"        =>    Ok((TokenType::Or, id, LoxType::Nil)),
-            "print"     =>    Ok((TokenType::Print, id, LoxType::Nil)),
-            "return"    =>    Ok((TokenType::Return, id, LoxType::Nil)),
-            "super"     =>    Ok((TokenType::Super, id, LoxType::Nil)),
-            "this"      =>    Ok((TokenType::This, id, LoxType::Nil)),
-            "true"      =>    Ok((TokenType::True, id, LoxType::Nil)),
-            "var"       =>    Ok((TokenType::Var, id, LoxType::Nil)),
-            "while"     =>    Ok((TokenType::While, id, LoxType::Nil)),
+        let id = String::from_utf8_lossy(&self.source[start_index..self.current]);
+        let symbol = Symbol::intern(&id);
+        match &*id {
+            "and"       =>    (TokenType::And, symbol, LoxType::Nil),
+            "class"     =>    (TokenType::Class, symbol, LoxType::Nil),
+            "else"      =>    (TokenType::Else, symbol, LoxType::Nil),
+            "false"     =>    (TokenType::False, symbol, LoxType::Nil),
+            "for"       =>    (TokenType::For, symbol, LoxType::Nil),
+            "fun"       =>    (TokenType::Fun, symbol, LoxType::Nil),
+            "if"        =>    (TokenType::If, symbol, LoxType::Nil),
+            "nil"       =>    (TokenType::Nil, symbol, LoxType::Nil),
+            "or"        =>    (TokenType::Or, symbol, LoxType::Nil),
+            "print"     =>    (TokenType::Print, symbol, LoxType::Nil),
+            "return"    =>    (TokenType::Return, symbol, LoxType::Nil),
+            "super"     =>    (TokenType::Super, symbol, LoxType::Nil),
+            "this"      =>    (TokenType::This, symbol, LoxType::Nil),
+            "true"      =>    (TokenType::True, symbol, LoxType::Nil),
+            "var"       =>    (TokenType::Var, symbol, LoxType::Nil),
+            "while"     =>    (TokenType::While, symbol, LoxType::Nil),
             _ => {
-                Ok((TokenType::Identifier, id.clone(), LoxType::Id(id)))
+                (TokenType::Identifier, symbol, LoxType::Id(symbol))
             }
         }
     }
@@ -251,13 +729,11 @@ impl Scanner {
 
     #[inline]
     fn is_digit(&self, c: u8) -> bool {
-        c >= b'0' && c <= b'9'
+        c.is_ascii_digit()
     }
 
     #[inline]
     fn is_alpha(&self, c: u8) -> bool {
-        c >= b'a' && c <= b'z' ||
-        c >= b'A' && c <= b'Z' ||
-        c == b'_'
+        c.is_ascii_alphabetic() || c == b'_'
     }
 }
\ No newline at end of file
@@ -1,33 +1,72 @@
-#[derive(Debug, Clone)]
+use std::fmt::Display;
+
+use crate::{intern::Symbol, number::format_float};
+
+#[derive(Debug, Clone, Copy)]
 pub struct Token {
     pub r#type: TokenType,
-    pub lexeme: Option<String>,
+    pub lexeme: Option<Symbol>,
     pub literal: LoxType,
-    pub line: u16
+    pub span: Span,
+    /// The `///` comment lines right before this token, without the slashes
+    /// and one space after them, joined by newlines.
+    pub doc: Option<Symbol>
+}
+
+impl Token {
+    /// The interned lexeme of an identifier or keyword token.
+    #[inline]
+    pub fn name(&self) -> Symbol {
+        self.lexeme.unwrap_or_default()
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Where a token sits in the source: the byte range `start..end`, plus the
+/// 1-based line and column (in characters) of `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum LoxType {
-    Id(String),
-    String(String),
+    Id(Symbol),
+    String(Symbol),
+    Int(i64),
     Number(f64),
     Nil,
 }
 
+impl Display for LoxType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxType::Id(id) => write!(f, "{}", id),
+            LoxType::String(str) => write!(f, "{:?}", str),
+            LoxType::Int(int) => write!(f, "{}", int),
+            LoxType::Number(num) => write!(f, "{}", format_float(*num)),
+            LoxType::Nil => write!(f, "nil")
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenType {
     // Single-character tokens.
     LeftParen, RightParen, LeftBrace, RightBrace,
-    Comma, Dot, Minus, Plus, Semicolon, Slash, Star,
+    Comma, Dot, Minus, Percent, Plus, Semicolon, Slash, Star,
   
     // One or two character tokens.
     Bang, BangEqual,
     Equal, EqualEqual,
     Greater, GreaterEqual,
     Less, LessEqual,
+    SlashSlash,
   
-    // Literals.
-    Identifier, String, Number,
+    // Literals. `Interpolation` is the part of a string before a `${`.
+    Identifier, String, Interpolation, Number,
   
     // Keywords.
     And, Class, Else, False, Fun, For, If, Nil, Or,
@@ -0,0 +1,222 @@
+//! How environments and instance fields store `LoxObject`s.
+//!
+//! By default a slot is the `LoxObject` itself. With the `nan-boxing` feature
+//! a slot is a single 64-bit word: numbers are stored as their IEEE bits,
+//! `nil`, the booleans and most integers live in the payload of one quiet
+//! NaN, and heap values are `Rc` pointers tucked into the payload of another. Values are
+//! converted back to `LoxObject` when they are read, so scripts and natives
+//! see exactly the same objects either way.
+
+use crate::ast::LoxObject;
+
+/// A stored value. `store` takes ownership of a `LoxObject`; `load` hands out
+/// a new reference to it, the same way cloning a `LoxObject` does.
+pub(crate) trait Slot {
+    fn store(value: LoxObject) -> Self;
+    fn load(&self) -> LoxObject;
+}
+
+#[cfg(not(feature = "nan-boxing"))]
+pub(crate) type Value = LoxObject;
+
+#[cfg(feature = "nan-boxing")]
+pub(crate) type Value = nan_box::NanBox;
+
+impl Slot for LoxObject {
+    #[inline]
+    fn store(value: LoxObject) -> Self {
+        value
+    }
+
+    #[inline]
+    fn load(&self) -> LoxObject {
+        self.clone()
+    }
+}
+
+#[cfg(all(feature = "nan-boxing", not(target_pointer_width = "64")))]
+compile_error!("the `nan-boxing` feature needs 64-bit pointers");
+
+#[cfg(feature = "nan-boxing")]
+mod nan_box {
+    use std::{cell::RefCell, marker::PhantomData, rc::Rc};
+
+    use super::Slot;
+    use crate::{ast::LoxObject, callable::{LoxFunction, LoxNative}, class::{LoxClass, LoxInstance}};
+
+    // A word is a number unless all of the QNAN bits are set. Heap values
+    // additionally set the sign bit; the rest are `nil`, `false` and `true`.
+    const QNAN: u64 = 0x7ffc_0000_0000_0000;
+    const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+    const OBJECT: u64 = QNAN | SIGN_BIT;
+
+    const NIL: u64 = QNAN | 1;
+    const FALSE: u64 = QNAN | 2;
+    const TRUE: u64 = QNAN | 3;
+
+    // Integers that fit in 48 bits are stored inline, after this tag. Larger
+    // ones are boxed like any other heap value.
+    const INT: u64 = QNAN | 0x0002_0000_0000_0000;
+    const INT_BITS: u32 = 48;
+    const INT_PAYLOAD: u64 = (1 << INT_BITS) - 1;
+
+    // Every pointee below is at least 8-byte aligned, which leaves the low
+    // three bits of the pointer free for the kind of object.
+    const POINTER_MASK: u64 = 0x0000_ffff_ffff_fff8;
+    const KIND_MASK: u64 = 0x7;
+
+    // A string value is an `Rc<Rc<str>>` here, whose outer pointer is thin.
+    const STRING: u64 = 0;
+    const FUNCTION: u64 = 1;
+    const NATIVE: u64 = 2;
+    const CLASS: u64 = 3;
+    const INSTANCE: u64 = 4;
+    const BIG_INT: u64 = 5;
+
+    pub(crate) struct NanBox {
+        bits: u64,
+        // Heap values are `Rc`s, which must not cross threads.
+        _rc: PhantomData<Rc<()>>
+    }
+
+    impl NanBox {
+        #[inline]
+        fn from_bits(bits: u64) -> NanBox {
+            NanBox { bits, _rc: PhantomData }
+        }
+
+        fn number(num: f64) -> NanBox {
+            // Canonicalize so a NaN produced at runtime can't collide with a tag.
+            let num = if num.is_nan() { f64::NAN } else { num };
+            NanBox::from_bits(num.to_bits())
+        }
+
+        fn int(int: i64) -> NanBox {
+            let payload = int as u64 & INT_PAYLOAD;
+            // Sign-extending the payload must give back the same integer.
+            if ((payload << (64 - INT_BITS)) as i64 >> (64 - INT_BITS)) == int {
+                NanBox::from_bits(INT | payload)
+            } else {
+                NanBox::object(Rc::new(int), BIG_INT)
+            }
+        }
+
+        fn object<T>(rc: Rc<T>, kind: u64) -> NanBox {
+            let pointer = Rc::into_raw(rc) as u64;
+            assert_eq!(pointer & !POINTER_MASK, 0, "pointer does not fit in a NaN box");
+            NanBox::from_bits(OBJECT | pointer | kind)
+        }
+
+        #[inline]
+        fn is_object(&self) -> bool {
+            self.bits & OBJECT == OBJECT
+        }
+
+        #[inline]
+        fn kind(&self) -> u64 {
+            self.bits & KIND_MASK
+        }
+
+        /// A new strong reference to the object this box points at.
+        ///
+        /// # Safety
+        /// The box must hold an object of kind `T`.
+        #[inline]
+        unsafe fn share<T>(&self) -> Rc<T> {
+            let pointer = (self.bits & POINTER_MASK) as *const T;
+            Rc::increment_strong_count(pointer);
+            Rc::from_raw(pointer)
+        }
+
+        /// # Safety
+        /// The box must hold an object of kind `T`, and must not be used
+        /// afterwards.
+        #[inline]
+        unsafe fn release<T>(&self) {
+            drop(Rc::from_raw((self.bits & POINTER_MASK) as *const T));
+        }
+    }
+
+    impl Slot for NanBox {
+        fn store(value: LoxObject) -> Self {
+            match value {
+                LoxObject::Int(int) => NanBox::int(int),
+                LoxObject::Number(num) => NanBox::number(num),
+                LoxObject::Bool(true) => NanBox::from_bits(TRUE),
+                LoxObject::Bool(false) => NanBox::from_bits(FALSE),
+                LoxObject::Nil => NanBox::from_bits(NIL),
+                LoxObject::String(str) => NanBox::object(str, STRING),
+                LoxObject::Function(function) => NanBox::object(function, FUNCTION),
+                LoxObject::Native(native) => NanBox::object(native, NATIVE),
+                LoxObject::Class(class) => NanBox::object(class, CLASS),
+                LoxObject::Instance(instance) => NanBox::object(instance, INSTANCE),
+            }
+        }
+
+        fn load(&self) -> LoxObject {
+            if !self.is_object() {
+                return match self.bits {
+                    NIL => LoxObject::Nil,
+                    FALSE => LoxObject::Bool(false),
+                    TRUE => LoxObject::Bool(true),
+                    bits if bits & !INT_PAYLOAD == INT => LoxObject::Int(((bits << (64 - INT_BITS)) as i64) >> (64 - INT_BITS)),
+                    bits => LoxObject::Number(f64::from_bits(bits))
+                };
+            }
+
+            // SAFETY: the kind bits were written by `store` together with a
+            // pointer from `Rc::into_raw` of that type, and this box still
+            // owns its reference.
+            unsafe {
+                match self.kind() {
+                    STRING => LoxObject::String(self.share::<Rc<str>>()),
+                    FUNCTION => LoxObject::Function(self.share::<LoxFunction>()),
+                    NATIVE => LoxObject::Native(self.share::<LoxNative>()),
+                    CLASS => LoxObject::Class(self.share::<LoxClass>()),
+                    INSTANCE => LoxObject::Instance(self.share::<RefCell<LoxInstance>>()),
+                    BIG_INT => LoxObject::Int(*self.share::<i64>()),
+                    _ => unreachable!("unknown object kind")
+                }
+            }
+        }
+    }
+
+    impl Clone for NanBox {
+        fn clone(&self) -> Self {
+            if self.is_object() {
+                // SAFETY: as in `load`; the new box owns the extra reference.
+                unsafe {
+                    match self.kind() {
+                        STRING => std::mem::forget(self.share::<Rc<str>>()),
+                        FUNCTION => std::mem::forget(self.share::<LoxFunction>()),
+                        NATIVE => std::mem::forget(self.share::<LoxNative>()),
+                        CLASS => std::mem::forget(self.share::<LoxClass>()),
+                        INSTANCE => std::mem::forget(self.share::<RefCell<LoxInstance>>()),
+                        BIG_INT => std::mem::forget(self.share::<i64>()),
+                        _ => unreachable!("unknown object kind")
+                    }
+                }
+            }
+            NanBox::from_bits(self.bits)
+        }
+    }
+
+    impl Drop for NanBox {
+        fn drop(&mut self) {
+            if self.is_object() {
+                // SAFETY: as in `load`; this gives back the box's own reference.
+                unsafe {
+                    match self.kind() {
+                        STRING => self.release::<Rc<str>>(),
+                        FUNCTION => self.release::<LoxFunction>(),
+                        NATIVE => self.release::<LoxNative>(),
+                        CLASS => self.release::<LoxClass>(),
+                        INSTANCE => self.release::<RefCell<LoxInstance>>(),
+                        BIG_INT => self.release::<i64>(),
+                        _ => unreachable!("unknown object kind")
+                    }
+                }
+            }
+        }
+    }
+}
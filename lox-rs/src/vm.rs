@@ -0,0 +1,583 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    compiler::{Constant, FunctionProto, OpCode},
+    error::LoxError,
+    number::{Number, Operator},
+    primitive::{is_equal, is_truthy, stringify, AsPrimitive, Primitive},
+};
+
+const FRAMES_MAX: usize = 1024;
+
+pub type VmNativeFn = dyn Fn(&[Value]) -> Result<Value, String>;
+
+/// A runtime value of the bytecode VM. It mirrors `LoxObject`, but callables
+/// carry compiled code instead of AST nodes.
+#[derive(Clone)]
+pub enum Value {
+    String(Rc<str>),
+    Int(i64),
+    Number(f64),
+    Bool(bool),
+    Closure(Rc<Closure>),
+    Native(Rc<Native>),
+    Class(Rc<RefCell<Class>>),
+    Instance(Rc<RefCell<Instance>>),
+    BoundMethod(Rc<BoundMethod>),
+    Nil,
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let r = match self {
+            Value::String(_) => "string",
+            Value::Int(_) => "int",
+            Value::Number(_) => "number",
+            Value::Bool(_) => "bool",
+            Value::Closure(_) | Value::BoundMethod(_) => "function",
+            Value::Native(_) => "native function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Nil => "nil",
+        };
+        write!(f, "{}", r)
+    }
+}
+
+impl Value {
+    #[inline]
+    fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Int(int) => Some(Number::Int(*int)),
+            Value::Number(num) => Some(Number::Float(*num)),
+            _ => None
+        }
+    }
+}
+
+impl AsPrimitive for Value {
+    #[inline]
+    fn as_primitive(&self) -> Primitive<'_> {
+        match self {
+            Value::String(str) => Primitive::String(Cow::Borrowed(str)),
+            Value::Int(int) => Primitive::Number(Number::Int(*int)),
+            Value::Number(num) => Primitive::Number(Number::Float(*num)),
+            Value::Bool(bool) => Primitive::Bool(*bool),
+            Value::Nil => Primitive::Nil,
+            Value::Closure(_) | Value::Native(_) | Value::Class(_) | Value::Instance(_) | Value::BoundMethod(_) => Primitive::Object
+        }
+    }
+
+    fn is_same_object(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Closure(l), Value::Closure(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::BoundMethod(l), Value::BoundMethod(r)) => Rc::ptr_eq(l, r),
+            _ => false
+        }
+    }
+
+    fn object_name(&self) -> String {
+        match self {
+            Value::Closure(closure) => function_name(&closure.function),
+            Value::Native(native) => format!("<native fn {}>", native.name),
+            Value::Class(class) => class.borrow().name.clone(),
+            Value::Instance(instance) => format!("{} instance", instance.borrow().class.borrow().name),
+            Value::BoundMethod(bound) => function_name(&bound.method.function),
+            _ => unreachable!("{} is not an object", self)
+        }
+    }
+}
+
+impl From<Number> for Value {
+    #[inline]
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(int) => Value::Int(int),
+            Number::Float(num) => Value::Number(num)
+        }
+    }
+}
+
+pub struct Closure {
+    function: Rc<FunctionProto>,
+    upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It points into the stack while the declaring
+/// function is running and holds the value itself once that slot is popped.
+enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Native {
+    name: String,
+    arity: usize,
+    function: Box<VmNativeFn>,
+}
+
+pub struct Class {
+    name: String,
+    methods: HashMap<Rc<str>, Rc<Closure>>,
+}
+
+pub struct Instance {
+    class: Rc<RefCell<Class>>,
+    fields: HashMap<Rc<str>, Value>,
+}
+
+pub struct BoundMethod {
+    receiver: Value,
+    method: Rc<Closure>,
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of slot 0: the callee, or the receiver of a method call.
+    base: usize,
+}
+
+/// Stack-based virtual machine that runs the output of `Compiler::compile`.
+/// Globals persist across calls to `interpret`, so one `Vm` can serve a
+/// whole REPL session.
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Default for Vm {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        let mut vm = Vm { stack: Vec::new(), frames: Vec::new(), globals: HashMap::new(), open_upvalues: Vec::new() };
+
+        vm.define_native("clock", 0, |_| {
+            let elapsed = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?;
+            Ok(Value::Number(elapsed.as_secs_f64()))
+        });
+
+        vm
+    }
+
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + 'static
+    {
+        let native = Native { name: name.to_owned(), arity, function: Box::new(function) };
+        self.globals.insert(name.into(), Value::Native(Rc::new(native)));
+    }
+
+    pub fn interpret(&mut self, function: Rc<FunctionProto>) -> Result<(), LoxError> {
+        let closure = Rc::new(Closure { function, upvalues: Vec::new() });
+        self.stack.push(Value::Closure(closure.clone()));
+        self.frames.push(CallFrame { closure, ip: 0, base: 0 });
+
+        let result = self.run();
+        if result.is_err() {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn run(&mut self) -> Result<(), LoxError> {
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                OpCode::Constant(index) => {
+                    let value = match self.constant(index) {
+                        Constant::Int(int) => Value::Int(*int),
+                        Constant::Number(num) => Value::Number(*num),
+                        Constant::String(str) => Value::String(str.clone()),
+                        Constant::Function(_) => unreachable!("functions are loaded by Closure")
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => { self.stack.pop(); }
+                OpCode::GetLocal(slot) => {
+                    let base = self.frame().base;
+                    self.stack.push(self.stack[base + slot].clone());
+                }
+                OpCode::SetLocal(slot) => {
+                    let base = self.frame().base;
+                    self.stack[base + slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal(index) => {
+                    let name = self.name(index);
+                    match self.globals.get(&name) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(self.error(format!("Undefined variable '{}'.", name)))
+                    }
+                }
+                OpCode::DefineGlobal(index) => {
+                    let name = self.name(index);
+                    let value = self.stack.pop().unwrap();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal(index) => {
+                    let name = self.name(index);
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(slot) => *slot = value,
+                        None => return Err(self.error(format!("Undefined variable '{}'.", name)))
+                    }
+                }
+                OpCode::GetUpvalue(index) => {
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone()
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue(index) => {
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let value = self.peek(0).clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value
+                    }
+                }
+                OpCode::GetProperty(index) => {
+                    let name = self.name(index);
+                    let instance = match self.peek(0) {
+                        Value::Instance(instance) => instance.clone(),
+                        _ => return Err(self.error("Only instances have properties.".to_owned()))
+                    };
+
+                    let field = instance.borrow().fields.get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => {
+                            let class = instance.borrow().class.clone();
+                            self.bind_method(&class, &name, Value::Instance(instance))?
+                        }
+                    };
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty(index) => {
+                    let name = self.name(index);
+                    let instance = match self.peek(1) {
+                        Value::Instance(instance) => instance.clone(),
+                        _ => return Err(self.error("Only instances have fields.".to_owned()))
+                    };
+
+                    let value = self.stack.pop().unwrap();
+                    instance.borrow_mut().fields.insert(name, value.clone());
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper(index) => {
+                    let name = self.name(index);
+                    let superclass = match self.stack.pop().unwrap() {
+                        Value::Class(class) => class,
+                        _ => return Err(self.error("'super' is not bound to a class.".to_owned()))
+                    };
+                    let receiver = self.stack.pop().unwrap();
+                    let value = self.bind_method(&superclass, &name, receiver)?;
+                    self.stack.push(value);
+                }
+                OpCode::Equal => {
+                    let r = self.stack.pop().unwrap();
+                    let l = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(is_equal(&l, &r)));
+                }
+                OpCode::NotEqual => {
+                    let r = self.stack.pop().unwrap();
+                    let l = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(!is_equal(&l, &r)));
+                }
+                OpCode::Greater => self.comparison(Ordering::is_gt)?,
+                OpCode::GreaterEqual => self.comparison(Ordering::is_ge)?,
+                OpCode::Less => self.comparison(Ordering::is_lt)?,
+                OpCode::LessEqual => self.comparison(Ordering::is_le)?,
+                OpCode::Add => {
+                    if let (Value::String(l), Value::String(r)) = (self.peek(1), self.peek(0)) {
+                        let str = format!("{}{}", l, r);
+                        self.stack.truncate(self.stack.len() - 2);
+                        self.stack.push(Value::String(str.into()));
+                    } else if self.peek(1).as_number().is_some() && self.peek(0).as_number().is_some() {
+                        self.arithmetic(Operator::Add)?;
+                    } else {
+                        return Err(self.error(format!("{} and {} must both be numbers or both be strings.", self.peek(1), self.peek(0))));
+                    }
+                }
+                OpCode::Subtract => self.arithmetic(Operator::Subtract)?,
+                OpCode::Multiply => self.arithmetic(Operator::Multiply)?,
+                OpCode::Divide => self.arithmetic(Operator::Divide)?,
+                OpCode::IntDivide => self.arithmetic(Operator::IntDivide)?,
+                OpCode::Remainder => self.arithmetic(Operator::Remainder)?,
+                OpCode::Not => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(!is_truthy(&value)));
+                }
+                OpCode::Negate => {
+                    let value = self.stack.pop().unwrap();
+                    match value.as_number() {
+                        Some(num) => {
+                            let num = num.negate().map_err(|msg| self.error(msg.to_owned()))?;
+                            self.stack.push(Value::from(num));
+                        }
+                        None => return Err(self.error(format!("{} must be a number.", value)))
+                    }
+                }
+                OpCode::Stringify => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(Value::String(stringify(&value).into()));
+                }
+                OpCode::Print => {
+                    let value = self.stack.pop().unwrap();
+                    println!("{}", stringify(&value));
+                }
+                OpCode::Jump(target) | OpCode::Loop(target) => self.frame_mut().ip = target,
+                OpCode::JumpIfFalse(target) => {
+                    if !is_truthy(self.peek(0)) {
+                        self.frame_mut().ip = target;
+                    }
+                }
+                OpCode::Call(count) => {
+                    let callee = self.peek(count).clone();
+                    self.call_value(callee, count)?;
+                }
+                OpCode::Closure(index) => {
+                    let function = match self.constant(index) {
+                        Constant::Function(function) => function.clone(),
+                        _ => unreachable!("Closure always refers to a function constant")
+                    };
+
+                    let base = self.frame().base;
+                    let mut upvalues = Vec::with_capacity(function.upvalues.len());
+                    for upvalue in &function.upvalues {
+                        if upvalue.is_local {
+                            upvalues.push(self.capture_upvalue(base + upvalue.index));
+                        } else {
+                            upvalues.push(self.frame().closure.upvalues[upvalue.index].clone());
+                        }
+                    }
+
+                    self.stack.push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Return => {
+                    let result = self.stack.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class(index) => {
+                    let name = self.name(index);
+                    let class = Class { name: name.to_string(), methods: HashMap::new() };
+                    self.stack.push(Value::Class(Rc::new(RefCell::new(class))));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Class(class) => class.clone(),
+                        _ => return Err(self.error("Superclass must be a class.".to_owned()))
+                    };
+                    if let Value::Class(subclass) = self.stack.pop().unwrap() {
+                        let methods = superclass.borrow().methods.clone();
+                        subclass.borrow_mut().methods.extend(methods);
+                    }
+                }
+                OpCode::Method(index) => {
+                    let name = self.name(index);
+                    if let (Value::Closure(method), Value::Class(class)) = (self.stack.pop().unwrap(), self.peek(0)) {
+                        class.borrow_mut().methods.insert(name, method);
+                    }
+                }
+            }
+        }
+    }
+
+    #[inline]
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    #[inline]
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    #[inline]
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    #[inline]
+    fn constant(&self, index: usize) -> &Constant {
+        &self.frame().closure.function.chunk.constants[index]
+    }
+
+    #[inline]
+    fn name(&self, index: usize) -> Rc<str> {
+        match self.constant(index) {
+            Constant::String(name) => name.clone(),
+            _ => unreachable!("names are always string constants")
+        }
+    }
+
+    /// Builds a runtime error located at the instruction being executed.
+    fn error(&self, msg: String) -> LoxError {
+        let frame = self.frame();
+        let span = frame.closure.function.chunk.spans[frame.ip - 1];
+        LoxError::RuntimeError { msg: msg.into(), span }
+    }
+
+    fn comparison(&mut self, test: fn(Ordering) -> bool) -> Result<(), LoxError> {
+        let r = self.stack.pop().unwrap();
+        let l = self.stack.pop().unwrap();
+        match (l.as_number(), r.as_number()) {
+            // NaN is neither less nor greater than anything.
+            (Some(l), Some(r)) => {
+                self.stack.push(Value::Bool(l.compare(r).is_some_and(test)));
+                Ok(())
+            }
+            _ => Err(self.error(format!("{} and {} must be numbers.", l, r)))
+        }
+    }
+
+    fn arithmetic(&mut self, operator: Operator) -> Result<(), LoxError> {
+        let r = self.stack.pop().unwrap();
+        let l = self.stack.pop().unwrap();
+        match (l.as_number(), r.as_number()) {
+            (Some(l), Some(r)) => {
+                let result = l.arithmetic(operator, r).map_err(|msg| self.error(msg.to_owned()))?;
+                self.stack.push(Value::from(result));
+                Ok(())
+            }
+            _ => Err(self.error(format!("{} and {} must be numbers.", l, r)))
+        }
+    }
+
+    fn call_value(&mut self, callee: Value, count: usize) -> Result<(), LoxError> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, count),
+            Value::Native(native) => {
+                self.check_arity(native.arity, count)?;
+                let start = self.stack.len() - count;
+                let result = (native.function)(&self.stack[start..]).map_err(|msg| self.error(msg))?;
+                self.stack.truncate(start - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            Value::Class(class) => {
+                let base = self.stack.len() - count - 1;
+                let instance = Instance { class: class.clone(), fields: HashMap::new() };
+                self.stack[base] = Value::Instance(Rc::new(RefCell::new(instance)));
+
+                let initializer = class.borrow().methods.get("init").cloned();
+                match initializer {
+                    Some(initializer) => self.call(initializer, count),
+                    None => self.check_arity(0, count)
+                }
+            }
+            Value::BoundMethod(bound) => {
+                let base = self.stack.len() - count - 1;
+                self.stack[base] = bound.receiver.clone();
+                self.call(bound.method.clone(), count)
+            }
+            _ => Err(self.error("Can only call functions and classes.".to_owned()))
+        }
+    }
+
+    fn check_arity(&self, arity: usize, count: usize) -> Result<(), LoxError> {
+        if arity != count {
+            return Err(self.error(format!("Expected {} arguments but got {}.", arity, count)));
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, count: usize) -> Result<(), LoxError> {
+        self.check_arity(closure.function.arity, count)?;
+
+        if self.frames.len() >= FRAMES_MAX {
+            return Err(self.error("Stack overflow.".to_owned()));
+        }
+
+        let base = self.stack.len() - count - 1;
+        self.frames.push(CallFrame { closure, ip: 0, base });
+        Ok(())
+    }
+
+    fn bind_method(&self, class: &Rc<RefCell<Class>>, name: &Rc<str>, receiver: Value) -> Result<Value, LoxError> {
+        match class.borrow().methods.get(name) {
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod { receiver, method: method.clone() }))),
+            None => Err(self.error(format!("Undefined property '{}'.", name)))
+        }
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self.open_upvalues
+            .iter()
+            .find(|upvalue| matches!(&*upvalue.borrow(), Upvalue::Open(open) if *open == slot));
+
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Moves every captured stack slot at or above `last` into its upvalue.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match &*upvalue.borrow() {
+                Upvalue::Open(slot) => *slot,
+                Upvalue::Closed(_) => return false
+            };
+            if slot < last {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+}
+
+#[inline]
+fn function_name(function: &FunctionProto) -> String {
+    match &function.name {
+        Some(name) => format!("<fn {}>", name),
+        None => "<script>".to_owned()
+    }
+}
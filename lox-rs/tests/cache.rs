@@ -0,0 +1,61 @@
+use lox_rs::{cache::{self, CacheError, FORMAT_VERSION}, parser::Parser, scanner::Scanner};
+
+const HEADER_LEN: usize = 22;
+
+fn compile(source: &str) -> Vec<u8> {
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    cache::save(&Parser::new(tokens).parse().expect("source should parse"))
+}
+
+/// Writes a valid checksum for an edited payload, so that only the decoder
+/// stands between it and the interpreter.
+fn reseal(bytes: &mut [u8]) {
+    let checksum = bytes[HEADER_LEN..].iter().fold(0xcbf29ce484222325, |hash: u64, &b| (hash ^ b as u64).wrapping_mul(0x100000001b3));
+    bytes[6..14].copy_from_slice(&checksum.to_le_bytes());
+}
+
+#[test]
+fn round_trips() {
+    let bytes = compile("print 1 + 2;");
+    assert!(cache::load(&bytes).is_ok());
+}
+
+#[test]
+fn flipped_payload_byte_is_a_checksum_mismatch() {
+    let bytes = compile("fun f(a) { return a * 2; } print f(21);");
+    for i in HEADER_LEN..bytes.len() {
+        let mut corrupt = bytes.clone();
+        corrupt[i] ^= 0x01;
+        assert_eq!(cache::load(&corrupt).err(), Some(CacheError::ChecksumMismatch), "byte {}", i);
+    }
+}
+
+#[test]
+fn truncated_file_is_an_error() {
+    let bytes = compile("class A < B { init() { this.x = \"${1}\"; } }");
+    for len in 0..bytes.len() {
+        assert!(cache::load(&bytes[..len]).is_err(), "length {}", len);
+    }
+    assert_eq!(cache::load(&bytes[..bytes.len() - 1]).err(), Some(CacheError::Truncated));
+}
+
+#[test]
+fn other_version_is_unsupported() {
+    let mut bytes = compile("print 1;");
+    bytes[4..6].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert_eq!(cache::load(&bytes).err(), Some(CacheError::UnsupportedVersion { found: FORMAT_VERSION + 1 }));
+}
+
+#[test]
+fn forward_node_id_is_malformed() {
+    // `1 + 2` is stored as two literals, 0 and 1, then the binary expression
+    // 2: tag 1, left 0, operator token 2, right 1. Point `right` at the
+    // binary expression itself.
+    let mut bytes = compile("print 1 + 2;");
+    let binary = [1, 0, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0];
+    let at = bytes.windows(binary.len()).position(|window| window == binary).expect("binary expression not found");
+    bytes[at + 9] = 2;
+    reseal(&mut bytes);
+
+    assert!(matches!(cache::load(&bytes), Err(CacheError::Malformed(msg)) if msg.contains("out of range")));
+}
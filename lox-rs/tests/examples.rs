@@ -0,0 +1,37 @@
+//! Runs every program in `example/` on both backends, with and without the
+//! optimizer, and compares what it prints with the `.out` file next to it. `cargo test --features nan-boxing`
+//! runs them against NaN-boxed slots too.
+
+use std::{fs, path::Path, process::Command};
+
+fn run(script: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .args(args)
+        .arg(script)
+        .output()
+        .expect("failed to run lox");
+    assert!(output.status.success(), "{} exited with {}", script.display(), output.status);
+    String::from_utf8(output.stdout).expect("output is not UTF-8")
+}
+
+#[test]
+fn examples() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("example");
+    let mut scripts: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no examples in {}", dir.display());
+
+    for script in scripts {
+        let expected = fs::read_to_string(script.with_extension("out"))
+            .unwrap_or_else(|e| panic!("{}: {}", script.with_extension("out").display(), e));
+
+        assert_eq!(run(&script, &[]), expected, "{} on the interpreter", script.display());
+        assert_eq!(run(&script, &["--vm"]), expected, "{} on the VM", script.display());
+        assert_eq!(run(&script, &["-O"]), expected, "{} optimized on the interpreter", script.display());
+        assert_eq!(run(&script, &["-O", "--vm"]), expected, "{} optimized on the VM", script.display());
+    }
+}
@@ -0,0 +1,69 @@
+//! The collector's heap is per thread, and so is each test, so the counters
+//! below only see what the test itself allocated.
+
+use lox_rs::{gc, interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::Scanner, LoxObject};
+
+fn interpreter() -> Interpreter {
+    let mut interpreter = Interpreter::new();
+    interpreter.define_native("check", 1, |args| match args[0] {
+        LoxObject::Bool(true) => Ok(LoxObject::Nil),
+        _ => Err("check failed.".to_owned())
+    });
+    interpreter
+}
+
+fn run(interpreter: &mut Interpreter, source: &str) {
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    let ast = Parser::new(tokens).parse().expect("source should parse");
+    let ast = Resolver::new().resolve(ast).expect("source should resolve");
+    if let Err(e) = interpreter.interpret(ast) {
+        panic!("{}", e.render(source.as_bytes()));
+    }
+}
+
+#[test]
+fn collects_cycles_and_keeps_what_is_reachable() {
+    let mut interpreter = interpreter();
+    run(&mut interpreter, r#"
+        // A closure stored in the environment it closes over.
+        fun leak() { var f; fun g() { return f; } f = g; }
+        // An instance holding itself and one of its own bound methods.
+        class A { init() { this.me = this; this.m = this.get; } get() { return 1; } }
+
+        var keep = A();
+        fun keeper() { var x = "kept"; fun h() { return x; } return h; }
+        var k = keeper();
+
+        for (var i = 0; i < 1000; i = i + 1) { leak(); A(); }
+    "#);
+
+    let before = gc::stats();
+    assert_eq!(before.collections, 0, "the leak is below the automatic threshold");
+    assert!(before.environments >= 1000 && before.instances >= 1000, "{:?}", before);
+
+    assert!(gc::collect() >= 2000);
+
+    let after = gc::stats();
+    assert!(after.environments <= before.environments - 1000, "{:?}", after);
+    assert_eq!(after.instances, 1, "only `keep` survives");
+
+    // What survived still works.
+    run(&mut interpreter, r#"
+        check(keep.me.m() == 1);
+        check(keep.me.me == keep);
+        check(k() == "kept");
+    "#);
+}
+
+#[test]
+fn collects_automatically() {
+    let mut interpreter = interpreter();
+    run(&mut interpreter, r#"
+        fun leak() { var f; fun g() { return f; } f = g; }
+        for (var i = 0; i < 50000; i = i + 1) { leak(); }
+    "#);
+
+    let stats = gc::stats();
+    assert!(stats.collections > 0);
+    assert!(stats.environments < 50000, "{:?}", stats);
+}
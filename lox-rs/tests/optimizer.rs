@@ -0,0 +1,53 @@
+use std::{env, fs, process::Command};
+
+use lox_rs::{optimizer::Optimizer, parser::Parser, printer::AstPrinter, resolver::Resolver, scanner::Scanner};
+
+/// The optimized program, printed one statement per line.
+fn optimize(source: &str) -> String {
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    let ast = Resolver::new().resolve(Parser::new(tokens).parse().expect("source should parse")).expect("source should resolve");
+    AstPrinter::new().print(&Optimizer::new().optimize(ast)).expect("program should print")
+}
+
+/// What the `lox` binary prints for `source`.
+fn run(name: &str, source: &str, args: &[&str]) -> String {
+    let script = env::temp_dir().join(format!("lox-optimizer-{}-{}.lox", name, std::process::id()));
+    fs::write(&script, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs")).args(args).arg(&script).output().expect("failed to run lox");
+    fs::remove_file(&script).unwrap();
+    String::from_utf8(output.stdout).expect("output is not UTF-8")
+}
+
+#[test]
+fn folds_literal_operands() {
+    assert_eq!(optimize("print 1 + 2 * 3;"), "(print 7)");
+    assert_eq!(optimize("print (7 // 2) - 0.5;"), "(print 2.5)");
+    assert_eq!(optimize("print \"a\" + \"b\";"), "(print \"ab\")");
+    assert_eq!(optimize("print !nil == (1 == 1.0);"), "(print true)");
+    assert_eq!(optimize("if (false) print 1; else print 2; while (nil) print 3;"), "(print 2)");
+}
+
+#[test]
+fn leaves_errors_for_runtime() {
+    assert_eq!(optimize("print 1 // 0;"), "(print (// 1 0))");
+    assert_eq!(optimize("print 1 % 0;"), "(print (% 1 0))");
+    assert_eq!(optimize("print \"a\" - 1;"), "(print (- \"a\" 1))");
+    assert_eq!(optimize("print -\"a\";"), "(print (- \"a\"))");
+    assert_eq!(optimize("print 9223372036854775807 + 1;"), "(print (+ 9223372036854775807 1))");
+}
+
+#[test]
+fn optimized_programs_print_the_same() {
+    let programs = [
+        "print 1 + 2 * 3 - 4 / 8; print 7 // 2 + 7 % 3; print -(1.5 * 2);",
+        "print \"a\" + 1 + nil + true; print !(1 < 2) == false; print 0.1 + 0.2;",
+        "var i = 0; while (i < 3) { if (1 > 2) print \"no\"; else print i; i = i + 1; }",
+        "print 1; print 1 // 0; print 2;",
+        "print \"a\" - 1;",
+        "print 9223372036854775807 + 1;",
+    ];
+    for (i, program) in programs.iter().enumerate() {
+        let name = i.to_string();
+        assert_eq!(run(&name, program, &["-O"]), run(&name, program, &[]), "{}", program);
+    }
+}
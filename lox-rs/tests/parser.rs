@@ -0,0 +1,53 @@
+use lox_rs::{error::LoxError, parser::Parser, resolver::Resolver, scanner::Scanner};
+
+/// The messages of every error reported before the program would run.
+fn errors(source: &str) -> Vec<String> {
+    let message = |e: LoxError| match e {
+        LoxError::ParseError { msg, .. } | LoxError::ResolveError { msg, .. } => msg.into(),
+        e => panic!("expected a ParseError or ResolveError, got {:?}", e)
+    };
+
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    match Parser::new(tokens).parse() {
+        Ok(ast) => Resolver::new().resolve(ast).err().into_iter().flatten().map(message).collect(),
+        Err(errors) => errors.into_iter().map(message).collect()
+    }
+}
+
+#[test]
+fn super_without_superclass_reports_one_error() {
+    assert_eq!(errors("class A { f() { super.f(); } }"), ["Can't use 'super' in a class with no superclass."]);
+}
+
+#[test]
+fn return_value_from_initializer_reports_one_error() {
+    assert_eq!(errors("class A { init() { return 1; } }"), ["Can't return a value from an initializer."]);
+}
+
+#[test]
+fn top_level_return_in_loop_reports_one_error() {
+    assert_eq!(errors("for (;;) { return; }"), ["Can't return from top-level code."]);
+}
+
+#[test]
+fn invalid_assignment_target_keeps_parsing() {
+    assert_eq!(errors("var a; var b; a + b = 3; print 1 = 2;"), ["Invalid assignment target.", "Invalid assignment target."]);
+}
+
+#[test]
+fn too_many_arguments_is_reported_once() {
+    let arguments = (0..300).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+    assert_eq!(errors(&format!("fun f() {{}} f({});", arguments)), ["Can't have more than 255 arguments."]);
+}
+
+#[test]
+fn too_many_parameters_is_reported_once() {
+    let params = (0..300).map(|i| format!("a{}", i)).collect::<Vec<_>>().join(", ");
+    assert_eq!(errors(&format!("fun f({}) {{}}", params)), ["Can't have more than 255 parameters."]);
+}
+
+#[test]
+fn slash_slash_after_an_operand_is_a_comment_outside_expressions() {
+    let source = "var x = 7 // 2;\nif (x) // not a division\n  print x; // nor this\nclass A < Object // it's a comment\n{}\n";
+    assert!(errors(&format!("class Object {{}}\n{}", source)).is_empty());
+}
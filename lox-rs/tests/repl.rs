@@ -0,0 +1,33 @@
+//! Drives the prompt through stdin, the way a user would.
+
+use std::{io::Write, process::{Command, Stdio}};
+
+/// Feeds `input` to the prompt and returns what it printed, without the
+/// prompts themselves.
+fn repl(input: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run lox");
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).expect("output is not UTF-8").replace("> ", "")
+}
+
+#[test]
+fn echoes_top_level_expressions() {
+    assert_eq!(repl("1 + 2;\n\"a\" + \"b\"\n"), "3\nab\n");
+}
+
+#[test]
+fn keeps_state_between_lines() {
+    assert_eq!(repl("var a = 1;\na = a + 1;\nprint a;\n"), "2\n2\n");
+}
+
+#[test]
+fn does_not_echo_nested_expressions() {
+    assert_eq!(repl("var a = 0;\n{ a + 1; }\nfor (a = 0; a < 2; a = a + 1) a;\nfun f() { 3; }\nprint f();\n"), "nil\n");
+}
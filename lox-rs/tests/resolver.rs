@@ -0,0 +1,114 @@
+use lox_rs::{
+    ast::{Ast, ExprId, Local, Stmt, StmtId},
+    error::LoxError,
+    parser::Parser,
+    resolver::Resolver,
+    scanner::Scanner,
+};
+
+fn resolve(source: &str) -> Result<Ast, Vec<LoxError>> {
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    Resolver::new().resolve(Parser::new(tokens).parse().expect("source should parse"))
+}
+
+/// The messages and lines of every resolve error in `source`.
+fn errors(source: &str) -> Vec<(String, u32)> {
+    match resolve(source) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .map(|e| match e {
+                LoxError::ResolveError { msg, span } => (msg.into(), span.line),
+                e => panic!("expected a ResolveError, got {:?}", e)
+            })
+            .collect()
+    }
+}
+
+/// The expression of every `print` in `statements`, in source order,
+/// looking inside blocks and function bodies.
+fn prints(ast: &Ast, statements: &[StmtId]) -> Vec<ExprId> {
+    statements
+        .iter()
+        .flat_map(|&stmt| match ast.stmt(stmt) {
+            Stmt::Print { expression } => vec![*expression],
+            Stmt::Block { statements } | Stmt::Function { body: statements, .. } => prints(ast, statements),
+            _ => Vec::new()
+        })
+        .collect()
+}
+
+/// Where each printed variable in `source` resolved to; `None` for globals.
+fn locals(source: &str) -> Vec<Option<(usize, usize)>> {
+    let ast = resolve(source).expect("source should resolve");
+    prints(&ast, ast.statements()).into_iter().map(|expr| ast.local(expr).map(|Local { depth, slot }| (depth, slot))).collect()
+}
+
+#[test]
+fn reports_every_error() {
+    let source = "
+        return 1;
+        print this;
+        print super.x;
+        class A < A {}
+        class B { f() { super.f(); } init() { return 1; } }
+        { var a = 1; var a = 2; }
+        { var b = b; }
+    ";
+    assert_eq!(errors(source), [
+        ("Can't return from top-level code.".to_owned(), 2),
+        ("Can't use 'this' outside of a class.".to_owned(), 3),
+        ("Can't use 'super' outside of a class.".to_owned(), 4),
+        ("A class can't inherit from itself.".to_owned(), 5),
+        ("Can't use 'super' in a class with no superclass.".to_owned(), 6),
+        ("Can't return a value from an initializer.".to_owned(), 6),
+        ("Already a variable with this name in this scope.".to_owned(), 7),
+        ("Can't read local variable in its own initializer.".to_owned(), 8),
+    ]);
+}
+
+#[test]
+fn valid_uses_are_not_errors() {
+    let source = "
+        var a = 1;
+        fun f() { return 1; }
+        class A { init() { return; } f() { return this; } }
+        class B < A { f() { return super.f(); } }
+    ";
+    assert_eq!(errors(source), []);
+}
+
+#[test]
+fn shadowed_locals_resolve_to_the_innermost_scope() {
+    let source = "
+        var g = 0;
+        {
+            var a = 1;
+            {
+                var b = 2;
+                var a = 3;
+                print a;
+                print b;
+            }
+            print a;
+        }
+        print g;
+    ";
+    assert_eq!(locals(source), [Some((0, 1)), Some((0, 0)), Some((0, 0)), None]);
+}
+
+#[test]
+fn closures_resolve_through_their_enclosing_scopes() {
+    let source = "
+        {
+            var a = 1;
+            fun f(x, y) {
+                print y;
+                print a;
+                { var z; print x; print z; }
+                fun g() { print f; print x; }
+            }
+        }
+    ";
+    assert_eq!(locals(source), [Some((0, 1)), Some((1, 0)), Some((1, 0)), Some((0, 0)), Some((2, 1)), Some((1, 0))]);
+}
@@ -0,0 +1,151 @@
+use lox_rs::{error::LoxError, scanner::Scanner, token::{Token, TokenType}};
+
+fn tokens(source: &str) -> Vec<Token> {
+    Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan")
+}
+
+fn types(source: &str) -> Vec<TokenType> {
+    tokens(source).into_iter().map(|token| token.r#type).collect()
+}
+
+fn lex_errors(source: &str) -> Vec<(String, usize, usize)> {
+    lex_errors_in(source.as_bytes())
+}
+
+fn lex_errors_in(source: &[u8]) -> Vec<(String, usize, usize)> {
+    match Scanner::new(source.to_vec()).scan_tokens() {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors
+            .into_iter()
+            .map(|e| {
+                let span = e.span();
+                match e {
+                    LoxError::LexError { msg, .. } => (msg.into(), span.start, span.end),
+                    e => panic!("expected a LexError, got {:?}", e)
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn empty_interpolation_between_string_parts() {
+    // Used to scan as `"a" + "b"` and concatenate with the next literal.
+    assert_eq!(lex_errors(r#"print "a${}b" "c";"#), [("字符串插值不能为空.".to_owned(), 8, 11)]);
+}
+
+#[test]
+fn empty_interpolation_alone() {
+    // Used to fail in the parser with "Expect expression." at the `"`.
+    assert_eq!(lex_errors(r#"print "${}";"#), [("字符串插值不能为空.".to_owned(), 7, 10)]);
+}
+
+#[test]
+fn interpolation_with_only_whitespace_and_comments() {
+    assert_eq!(lex_errors(r#"print "${ /* x */ }";"#).len(), 1);
+}
+
+#[test]
+fn nested_interpolations_are_not_empty() {
+    assert!(lex_errors(r#"print "a${1}b${"c${2}"}";"#).is_empty());
+}
+
+#[test]
+fn doc_comment_attaches_to_the_next_declaration() {
+    let source = "var a; // not a doc\n/// Adds.\n///   Twice.\nfun add() {}\n//// not a doc either\nvar b;";
+    let docs: Vec<_> = tokens(source).into_iter().map(|token| (token.r#type, token.doc.map(|doc| doc.to_string()))).filter(|(_, doc)| doc.is_some()).collect();
+    assert_eq!(docs, [(TokenType::Fun, Some("Adds.\n  Twice.".to_owned()))]);
+}
+
+#[test]
+fn doc_comment_at_the_end_attaches_to_eof() {
+    let tokens = tokens("print 1;\n/// Trailing.");
+    assert_eq!(tokens.last().unwrap().doc.map(|doc| doc.to_string()), Some("Trailing.".to_owned()));
+}
+
+#[test]
+fn block_comments_nest() {
+    assert_eq!(types("/* a /* b */ c */ print /* */ 1;"), [TokenType::Print, TokenType::Number, TokenType::Semicolon, TokenType::Eof]);
+    assert_eq!(types("/* /* */ */"), [TokenType::Eof]);
+}
+
+#[test]
+fn unterminated_nested_block_comment() {
+    assert_eq!(lex_errors("/* /* */ print 1;"), [("块注释没有结束.".to_owned(), 0, 2)]);
+}
+
+#[test]
+fn errors_after_invalid_utf8_render_at_their_span() {
+    let source = b"print 1;\n\xff\xff\xff \"${}\";";
+    let errors = Scanner::new(source.to_vec()).scan_tokens().unwrap_err();
+    let rendered = errors.last().unwrap().render(source);
+    assert_eq!(rendered.lines().skip(3).collect::<Vec<_>>(), ["2 | \u{fffd}\u{fffd}\u{fffd} \"${}\";", "  |      ^^^"]);
+}
+
+#[test]
+fn invalid_utf8_is_reported_where_it_is() {
+    const INVALID: &str = "无效的 UTF-8 编码.";
+    assert_eq!(lex_errors_in(b"print \"a\xffb\";"), [(INVALID.to_owned(), 8, 9)]);
+    // A truncated sequence in a comment, and one cut off by the end of the file.
+    assert_eq!(lex_errors_in(b"// \xc3\n\xe4\xb8"), [(INVALID.to_owned(), 3, 4), (INVALID.to_owned(), 5, 7)]);
+    assert_eq!(lex_errors_in(b"x\xff\xfey"), [(INVALID.to_owned(), 1, 2), (INVALID.to_owned(), 2, 3)]);
+}
+
+#[test]
+fn arbitrary_bytes_never_panic() {
+    // Mostly bytes that start or continue Lox tokens and UTF-8 sequences.
+    const BYTES: &[u8] = b"\"${}/*0x1_e.a \n\x80\xc3\xe4\xf0\xff";
+
+    let mut seed = 0x2545f4914f6cdd1d_u64;
+    for _ in 0..5000 {
+        let source: Vec<u8> = (0..24).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            BYTES[seed as usize % BYTES.len()]
+        }).collect();
+        let _ = Scanner::new(source).scan_tokens();
+    }
+}
+
+#[test]
+fn unicode_identifiers() {
+    let identifiers = |source: &str| -> Vec<String> {
+        tokens(source).into_iter().filter(|token| token.r#type == TokenType::Identifier).map(|token| token.name().to_string()).collect()
+    };
+    assert_eq!(identifiers("var 名字 = 1; print 名字;"), ["名字", "名字"]);
+    // A combining mark may continue an identifier, but not start one.
+    assert_eq!(identifiers("var e\u{301}t\u{e9} = _x1;"), ["e\u{301}t\u{e9}", "_x1"]);
+    assert_eq!(lex_errors("print \u{301};"), [("未知的词素.".to_owned(), 6, 8)]);
+    assert_eq!(lex_errors("var \u{1f600} = 1;"), [("未知的词素.".to_owned(), 4, 8)]);
+}
+
+#[test]
+fn numeric_literals() {
+    let literals = |source: &str| -> Vec<String> {
+        tokens(source).into_iter().filter(|token| token.r#type == TokenType::Number).map(|token| token.literal.to_string()).collect()
+    };
+    assert_eq!(
+        literals("0xff 0b101 0o17 1_000_000 1e3 2.5E-1 1.0 9223372036854775807"),
+        ["255", "5", "15", "1000000", "1000.0", "0.25", "1.0", "9223372036854775807"]
+    );
+}
+
+#[test]
+fn malformed_numeric_literals() {
+    let cases = [
+        ("print 0x;", "十六进制字面量缺少数字.", 6, 8),
+        ("print 0b2;", "不是有效的二进制数字.", 8, 9),
+        ("print 0o8;", "不是有效的八进制数字.", 8, 9),
+        ("print 1__0;", "只能出现在两个数字之间.", 7, 8),
+        ("print 1_;", "只能出现在两个数字之间.", 7, 8),
+        ("print 1e;", "指数部分缺少数字.", 7, 8),
+        ("print 1e+;", "指数部分缺少数字.", 7, 9),
+        ("print 9223372036854775808;", "整数超出范围.", 6, 25),
+        ("print 0x1_0000_0000_0000_0000;", "整数超出范围.", 6, 29),
+        ("print 1e999;", "数字超出范围.", 6, 11),
+    ];
+    for (source, msg, start, end) in cases {
+        assert_eq!(lex_errors(source), [(msg.to_owned(), start, end)], "{}", source);
+    }
+}
@@ -0,0 +1,67 @@
+//! Deep recursion and deep nesting must end in a Lox error, never in the
+//! process aborting on a native stack overflow. These run the `lox` binary,
+//! since the stack it runs the interpreter on is part of what is tested.
+
+use std::{env, fs, process::Command};
+
+/// Runs `source` and returns what it printed. Fails if the process was
+/// killed, which is how a native stack overflow ends.
+fn run(name: &str, source: &str, args: &[&str]) -> String {
+    let script = env::temp_dir().join(format!("lox-stack-{}-{}.lox", name, std::process::id()));
+    fs::write(&script, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lox-rs"))
+        .args(args)
+        .arg(&script)
+        .output()
+        .expect("failed to run lox");
+    fs::remove_file(&script).unwrap();
+
+    assert!(output.status.code().is_some(), "lox was killed: {}\n{}", output.status, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).expect("output is not UTF-8")
+}
+
+/// A function that recurses `calls` times from inside `blocks` nested
+/// `while` loops and blocks.
+fn nested_recursion(blocks: usize, calls: usize) -> String {
+    let mut body = "if (n > 0) return f(n - 1) + 1; return 0;".to_owned();
+    for _ in 0..blocks {
+        body = format!("{{ while (true) {{ {} }} }}", body);
+    }
+    format!("fun f(n) {{ {} }}\nprint f({});\n", body, calls)
+}
+
+#[test]
+fn deep_recursion_through_loops_and_blocks_runs() {
+    let source = nested_recursion(1, 1000);
+    for args in [&[][..], &["--vm"]] {
+        assert_eq!(run("recursion", &source, args), "1000\n", "{:?}", args);
+    }
+}
+
+#[test]
+fn unbounded_recursion_is_a_stack_overflow() {
+    let source = "fun f(n) { return f(n + 1); }\nf(0);\n";
+    for args in [&[][..], &["--vm"]] {
+        let output = run("unbounded", source, args);
+        assert!(output.contains("Stack overflow."), "{:?}: {}", args, output);
+    }
+}
+
+#[test]
+fn deep_nesting_in_recursion_is_a_stack_overflow() {
+    let output = run("nesting", &nested_recursion(200, 1000), &[]);
+    assert!(output.contains("RunTimeError: Stack overflow."), "{}", output);
+}
+
+#[test]
+fn deep_nesting_in_source_is_a_parse_error() {
+    let depth = 100_000;
+    let expression = format!("print {}1{};\n", "(".repeat(depth), ")".repeat(depth));
+    let blocks = format!("{}print 1;{}\n", "{".repeat(depth), "}".repeat(depth));
+
+    for source in [expression, blocks] {
+        let output = run("source", &source, &["--plain"]);
+        assert_eq!(output, "[line 1] ParseError Too much nesting.\n");
+    }
+}
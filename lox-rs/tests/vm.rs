@@ -0,0 +1,29 @@
+use std::{cell::RefCell, rc::Rc};
+
+use lox_rs::{compiler::Compiler, parser::Parser, resolver::Resolver, scanner::Scanner, vm::{Value, Vm}};
+
+fn run(vm: &mut Vm, source: &str) {
+    let tokens = Scanner::new(source.as_bytes().to_vec()).scan_tokens().expect("source should scan");
+    let ast = Resolver::new().resolve(Parser::new(tokens).parse().expect("source should parse")).expect("source should resolve");
+    let function = Compiler::new().compile(&ast).expect("source should compile");
+    if let Err(e) = vm.interpret(function) {
+        panic!("{}", e.render(source.as_bytes()));
+    }
+}
+
+#[test]
+fn natives_can_capture_state() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = Vm::new();
+    let log = Rc::clone(&seen);
+    vm.define_native("record", 1, move |args| {
+        match &args[0] {
+            Value::Int(int) => log.borrow_mut().push(*int),
+            _ => return Err("record takes an integer.".to_owned())
+        }
+        Ok(Value::Nil)
+    });
+
+    run(&mut vm, "for (var i = 0; i < 3; i = i + 1) record(i * 10);");
+    assert_eq!(*seen.borrow(), [0, 10, 20]);
+}